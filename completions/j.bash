@@ -8,7 +8,7 @@ _j_completions() {
     local prev="${COMP_WORDS[COMP_CWORD-1]}"
     
     # Options
-    local options="-i -c -x -xa -l -a -ar -al -h --help -V --version --exclude-add --exclude-remove --exclude-list --complete"
+    local options="-i -p -c -x -xa -l -a -ar -al -h --help -V --version --exclude-add --exclude-remove --exclude-list --index --import --import-shell-history --export --merge --complete --explain --doctor"
     
     # If current word starts with -, complete options
     if [[ "$cur" == -* ]]; then
@@ -21,6 +21,22 @@ _j_completions() {
         -a|-ar|--exclude-add|--exclude-remove)
             return
             ;;
+        --index)
+            COMPREPLY=($(compgen -W "add remove list update" -- "$cur"))
+            return
+            ;;
+        --import)
+            COMPREPLY=($(compgen -W "autojump z fasd zoxide" -- "$cur"))
+            return
+            ;;
+        --merge|--import-shell-history)
+            COMPREPLY=($(compgen -f -- "$cur"))
+            return
+            ;;
+        --format|-f)
+            COMPREPLY=($(compgen -W "json csv z zoxide" -- "$cur"))
+            return
+            ;;
     esac
     
     # Get directory completions from j --complete
@@ -0,0 +1,154 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::Instant;
+
+use crate::{Config, HistoryEntry};
+
+/// Fraction of `aging_threshold` the total visit count is scaled down to
+const AGING_TARGET: f64 = 0.9;
+
+/// Factor to multiply every visit count by, or `None` while the total visit
+/// count is still within `aging_threshold`
+pub fn scale_factor(total_visits: u64, config: &Config) -> Option<f64> {
+    let threshold = config.aging_threshold();
+    if threshold == 0 || total_visits <= threshold {
+        return None;
+    }
+    Some(AGING_TARGET * threshold as f64 / total_visits as f64)
+}
+
+/// Scaled visit count, or `None` if the entry falls below `aging_min_score`
+pub fn scale_count(visit_count: u32, factor: f64, config: &Config) -> Option<u32> {
+    let scaled = visit_count as f64 * factor;
+    if scaled < config.aging_min_score {
+        None
+    } else {
+        Some(scaled.round().max(1.0) as u32)
+    }
+}
+
+/// Entries aging dropped from history. Kept with the state and reported by
+/// `j --doctor`: anything printed while saving would end up in the output
+/// the shell wrappers read the jump target from.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Dropped {
+    /// Scaled below `aging_min_score`
+    pub rare: u64,
+    /// Missing for longer than `missing_retention_days`
+    pub missing: u64,
+    /// When an aging pass last dropped anything
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last: Option<DateTime<Utc>>,
+}
+
+impl Dropped {
+    pub fn is_empty(&self) -> bool {
+        self.rare == 0 && self.missing == 0
+    }
+
+    /// Add the entries dropped by another pass
+    pub fn add(&mut self, other: &Dropped) {
+        self.rare += other.rare;
+        self.missing += other.missing;
+        self.last = self.last.max(other.last);
+    }
+}
+
+/// Result of checking whether a history entry still exists
+#[derive(Debug, PartialEq)]
+pub enum Presence {
+    Present,
+    /// Missing, and first seen missing at the given time
+    Missing(DateTime<Utc>),
+    /// Missing for longer than `missing_retention_days`
+    Expired,
+}
+
+/// Check one entry and work out its new `missing_since`
+pub fn check_presence(
+    path: &str,
+    missing_since: Option<DateTime<Utc>>,
+    now: DateTime<Utc>,
+    config: &Config,
+) -> Presence {
+    if Path::new(path).is_dir() {
+        return Presence::Present;
+    }
+    let since = missing_since.unwrap_or(now);
+    let retention = Duration::days(config.missing_retention_days as i64);
+    if config.missing_retention_days > 0 && now - since >= retention {
+        Presence::Expired
+    } else {
+        Presence::Missing(since)
+    }
+}
+
+/// Time limit for the filesystem checks of one aging pass
+pub struct Budget {
+    deadline: Instant,
+}
+
+impl Budget {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            deadline: Instant::now() + std::time::Duration::from_millis(config.aging_budget_ms),
+        }
+    }
+
+    pub fn exhausted(&self) -> bool {
+        Instant::now() >= self.deadline
+    }
+}
+
+/// Run an aging pass over an in-memory history: scale visit counts once the
+/// total passes `aging_threshold`, drop entries below `aging_min_score`, and
+/// drop entries whose directory has been missing for `missing_retention_days`.
+/// Existence checks start at a rotating offset and stop when the budget runs out,
+/// so successive passes cover the whole history.
+pub fn age_history(history: &mut Vec<HistoryEntry>, config: &Config) -> Dropped {
+    let mut dropped = Dropped::default();
+    let total: u64 = history.iter().map(|e| e.visit_count as u64).sum();
+    if let Some(factor) = scale_factor(total, config) {
+        let before = history.len();
+        history.retain_mut(|entry| match scale_count(entry.visit_count, factor, config) {
+            Some(count) => {
+                entry.visit_count = count;
+                true
+            }
+            None => false,
+        });
+        dropped.rare = (before - history.len()) as u64;
+    }
+    let now = Utc::now();
+    if dropped.rare > 0 {
+        dropped.last = Some(now);
+    }
+
+    if history.is_empty() {
+        return dropped;
+    }
+    let budget = Budget::new(config);
+    let len = history.len();
+    let start = now.timestamp().unsigned_abs() as usize % len;
+    let mut expired = vec![false; len];
+    for offset in 0..len {
+        if budget.exhausted() {
+            break;
+        }
+        let i = (start + offset) % len;
+        let entry = &mut history[i];
+        match check_presence(&entry.path, entry.missing_since, now, config) {
+            Presence::Present => entry.missing_since = None,
+            Presence::Missing(since) => entry.missing_since = Some(since),
+            Presence::Expired => expired[i] = true,
+        }
+    }
+    dropped.missing = expired.iter().filter(|&&expired| expired).count() as u64;
+    if dropped.missing > 0 {
+        dropped.last = Some(now);
+    }
+    let mut expired = expired.into_iter();
+    history.retain(|_| !expired.next().unwrap_or(false));
+    dropped
+}
@@ -0,0 +1,308 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::aging;
+use crate::schema::Versioned;
+use crate::storage::json::{self, try_read_json};
+use crate::storage::{self, sync, StorageKind};
+use crate::rewrite;
+use crate::{Aliases, AppState, Config};
+
+/// Number of dead entries listed before the rest are summarized
+const MAX_LISTED: usize = 10;
+
+#[derive(Default)]
+struct Report {
+    problems: usize,
+}
+
+impl Report {
+    fn ok(&self, msg: &str) {
+        eprintln!("[ok]    {}", msg);
+    }
+
+    fn warn(&mut self, msg: &str) {
+        self.problems += 1;
+        eprintln!("[warn]  {}", msg);
+    }
+
+    fn error(&mut self, msg: &str) {
+        self.problems += 1;
+        eprintln!("[error] {}", msg);
+    }
+}
+
+/// Validate a persisted file, returning its contents if it parses
+fn check_file<T: Versioned>(
+    report: &mut Report,
+    name: &str,
+    path: io::Result<PathBuf>,
+) -> Option<T> {
+    let path = match path {
+        Ok(path) => path,
+        Err(err) => {
+            report.error(&format!("{}: cannot resolve location: {}", name, err));
+            return None;
+        }
+    };
+
+    let value = match try_read_json::<T>(&path) {
+        Ok(Some(value)) => {
+            let version = json::file_version(&path).unwrap_or(0);
+            if version > T::VERSION {
+                report.error(&format!(
+                    "{}: {} uses schema v{}, newer than this j (v{}); it will not be written",
+                    name,
+                    path.display(),
+                    version,
+                    T::VERSION
+                ));
+            } else {
+                report.ok(&format!("{}: {} (schema v{})", name, path.display(), version));
+            }
+            Some(value)
+        }
+        Ok(None) => {
+            report.ok(&format!("{}: {} (not created yet)", name, path.display()));
+            None
+        }
+        Err(err) => {
+            report.error(&format!("{}: {}: {}", name, path.display(), err));
+            None
+        }
+    };
+
+    for file in json::quarantined_files(&path) {
+        report.warn(&format!("{}: quarantined corrupt copy {}", name, file.display()));
+    }
+    let backups = json::list_backups(&path);
+    if let Some(newest) = backups.first() {
+        eprintln!(
+            "        {} backup(s), newest {}",
+            backups.len(),
+            newest.display()
+        );
+    }
+
+    value
+}
+
+fn check_json_files(report: &mut Report) -> (Option<AppState>, Option<Aliases>) {
+    let state = check_file(report, "state.json", storage::get_state_path());
+    let aliases = check_file(report, "aliases.json", storage::get_aliases_path());
+    (state, aliases)
+}
+
+#[cfg(feature = "sqlite")]
+fn check_sqlite(report: &mut Report) -> (Option<AppState>, Option<Aliases>) {
+    use crate::storage::sqlite::SqliteStore;
+    use crate::storage::Store;
+
+    let store = match SqliteStore::open() {
+        Ok(store) => store,
+        Err(err) => {
+            report.error(&format!("state.db: cannot open: {}", err));
+            return (None, None);
+        }
+    };
+    match store.check() {
+        Ok(description) => report.ok(&format!("state.db: {}", description)),
+        Err(err) => report.error(&format!("state.db: {}", err)),
+    }
+    (Some(store.load_state()), Some(store.load_aliases()))
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn check_sqlite(report: &mut Report) -> (Option<AppState>, Option<Aliases>) {
+    report.error(
+        "config.json selects sqlite storage, but this j was built without the `sqlite` feature; using JSON files",
+    );
+    check_json_files(report)
+}
+
+/// Check every host file in the sync directory
+fn check_sync(report: &mut Report, config: &Config) {
+    let Some(dir) = sync::sync_dir(config) else {
+        return;
+    };
+    if !dir.is_dir() {
+        report.warn(&format!("sync: {} does not exist", dir.display()));
+        return;
+    }
+    let own = sync::host_file(&dir);
+    if !own.exists() {
+        report.ok(&format!("sync: {} (not created yet)", own.display()));
+    }
+    for file in sync::host_files(&dir) {
+        let name = file.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        let label = if file == own {
+            format!("sync {} (this machine)", name)
+        } else {
+            format!("sync {}", name)
+        };
+        check_file::<AppState>(report, &label, Ok(file));
+    }
+}
+
+fn report_dead(report: &mut Report, what: &str, dead: &[String]) {
+    if dead.is_empty() {
+        report.ok(&format!("no dead {}", what));
+        return;
+    }
+    report.warn(&format!("{} dead {}:", dead.len(), what));
+    for line in dead.iter().take(MAX_LISTED) {
+        eprintln!("        {}", line);
+    }
+    if dead.len() > MAX_LISTED {
+        eprintln!("        ... and {} more", dead.len() - MAX_LISTED);
+    }
+}
+
+fn check_fzf(report: &mut Report) {
+    let fzf_cmd = if cfg!(windows) { "fzf.exe" } else { "fzf" };
+    match Command::new(fzf_cmd).arg("--version").output() {
+        Ok(output) if output.status.success() => {
+            let version = String::from_utf8_lossy(&output.stdout);
+            report.ok(&format!("fzf {}", version.trim()));
+        }
+        _ => report.warn("fzf not found (needed for j -i)"),
+    }
+}
+
+/// Shell startup files that may install the `j -c` hook
+fn shell_rc_files() -> Vec<PathBuf> {
+    let home = match dirs::home_dir() {
+        Some(home) => home,
+        None => return Vec::new(),
+    };
+    let mut files = vec![
+        home.join(".zshrc"),
+        home.join(".bashrc"),
+        home.join(".bash_profile"),
+        home.join(".profile"),
+        home.join(".config").join("fish").join("config.fish"),
+    ];
+    if let Some(zdotdir) = std::env::var_os("ZDOTDIR") {
+        files.push(PathBuf::from(zdotdir).join(".zshrc"));
+    }
+    if let Some(documents) = dirs::document_dir() {
+        files.push(
+            documents
+                .join("PowerShell")
+                .join("Microsoft.PowerShell_profile.ps1"),
+        );
+    }
+    files
+}
+
+/// Whether a shell line records the current directory with `j -c`
+/// or sources one of the bundled j-init scripts that does
+fn is_hook_line(line: &str) -> bool {
+    let line = line.trim();
+    if line.starts_with('#') {
+        return false;
+    }
+    if line.contains("j-init") {
+        return true;
+    }
+    let words: Vec<&str> = line.split_whitespace().collect();
+    words.windows(2).any(|pair| {
+        let cmd = pair[0].trim_matches('"');
+        pair[1] == "-c"
+            && (cmd == "j"
+                || cmd.ends_with("/j")
+                || cmd.ends_with("j.exe")
+                || cmd.contains("J_CMD"))
+    })
+}
+
+fn check_shell_hook(report: &mut Report) {
+    for file in shell_rc_files() {
+        let contents = match std::fs::read_to_string(&file) {
+            Ok(contents) => contents,
+            Err(_) => continue,
+        };
+        if let Some((num, _)) = contents
+            .lines()
+            .enumerate()
+            .find(|(_, line)| is_hook_line(line))
+        {
+            report.ok(&format!(
+                "shell hook found in {}:{}",
+                file.display(),
+                num + 1
+            ));
+            return;
+        }
+    }
+    report.warn("no shell hook calling `j -c` found; history is only recorded on jumps");
+}
+
+/// Describe what aging has dropped; informational, since it is by design
+fn report_aged_out(report: &Report, dropped: &aging::Dropped) {
+    let Some(last) = dropped.last else {
+        return;
+    };
+    report.ok(&format!(
+        "aging has dropped {} rarely visited and {} long-missing directories, most recently {}",
+        dropped.rare,
+        dropped.missing,
+        last.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M")
+    ));
+}
+
+/// Check data files, dead entries and the environment j depends on
+pub fn run() {
+    let mut report = Report::default();
+
+    eprintln!("Files:");
+    let mut config: Option<Config> =
+        check_file(&mut report, "config.json", storage::get_config_path());
+    if let Some(config) = &mut config {
+        for problem in config.sanitize() {
+            report.warn(&format!("config.json: {}", problem));
+        }
+    }
+    let (state, aliases) = match config.as_ref().map(|c| c.storage).unwrap_or_default() {
+        StorageKind::Json => check_json_files(&mut report),
+        StorageKind::Sqlite => check_sqlite(&mut report),
+    };
+    let config = config.unwrap_or_default();
+    check_sync(&mut report, &config);
+
+    eprintln!();
+    eprintln!("Entries:");
+    if let Some(state) = &state {
+        let dead: Vec<String> = state
+            .history
+            .iter()
+            .filter(|e| !Path::new(&e.path).is_dir())
+            .map(|e| e.path.clone())
+            .collect();
+        report_dead(&mut report, "history entries", &dead);
+        report_aged_out(&report, &state.aged_out);
+    }
+    if let Some(aliases) = &aliases {
+        let mut dead: Vec<String> = aliases
+            .map
+            .iter()
+            .filter(|(_, path)| !Path::new(&rewrite::localize(path, &config)).is_dir())
+            .map(|(name, path)| format!("!{} -> {}", name, path))
+            .collect();
+        dead.sort();
+        report_dead(&mut report, "aliases", &dead);
+    }
+
+    eprintln!();
+    eprintln!("Environment:");
+    check_fzf(&mut report);
+    check_shell_hook(&mut report);
+
+    eprintln!();
+    if report.problems == 0 {
+        eprintln!("No problems found");
+    } else {
+        eprintln!("{} problem(s) found", report.problems);
+    }
+}
@@ -0,0 +1,253 @@
+use chrono::{DateTime, Utc};
+use std::path::Path;
+
+use crate::index;
+use crate::proximity::Proximity;
+use crate::query::Query;
+use crate::rank::{self, Candidate, MatchTier};
+use crate::rewrite;
+use crate::storage::load_aliases;
+use crate::typo;
+use crate::walk;
+use crate::{try_local_path, AppState, Config};
+
+/// Why a matching history entry can or cannot be selected
+enum Status {
+    Ok,
+    Excluded,
+    NotADirectory,
+}
+
+impl Status {
+    fn of(candidate: &Candidate) -> Status {
+        if candidate.excluded {
+            Status::Excluded
+        } else if !Path::new(&candidate.path).is_dir() {
+            Status::NotADirectory
+        } else {
+            Status::Ok
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            Status::Ok => "",
+            Status::Excluded => " [excluded]",
+            Status::NotADirectory => " [not a directory]",
+        }
+    }
+}
+
+/// Note on where a candidate came from, empty for a plain history entry
+fn via(candidate: &Candidate) -> String {
+    match (&candidate.via, candidate.implicit) {
+        (Some(path), false) => format!(" (via {})", path),
+        (Some(path), true) => format!(" (via implicit {})", path),
+        (None, true) => " (implicit)".to_string(),
+        (None, false) => String::new(),
+    }
+}
+
+/// Format the time since the last visit as a short human-readable age
+fn format_age(last_visited: DateTime<Utc>, now: DateTime<Utc>) -> String {
+    let age = now - last_visited;
+    if age.num_days() > 0 {
+        format!("{}d", age.num_days())
+    } else if age.num_hours() > 0 {
+        format!("{}h", age.num_hours())
+    } else if age.num_minutes() > 0 {
+        format!("{}m", age.num_minutes())
+    } else {
+        format!("{}s", age.num_seconds().max(0))
+    }
+}
+
+fn print_rows(rows: &[&Candidate], selected: Option<&Candidate>) {
+    if rows.is_empty() {
+        eprintln!("    (no candidates)");
+        return;
+    }
+    eprintln!(
+        "    {:>9}  {:>8}  {:>6}  {:>6}  {:>5}  path",
+        "score", "frecency", "visits", "age", "near"
+    );
+    let now = Utc::now();
+    for row in rows {
+        let marker = if selected.is_some_and(|s| std::ptr::eq(s, *row)) {
+            " <- selected"
+        } else {
+            Status::of(row).label()
+        };
+        let visits = row.visits.map_or("-".to_string(), |v| v.to_string());
+        let project = if row.project { " [project]" } else { "" };
+        eprintln!(
+            "    {:>9.2}  {:>8.2}  {:>6}  {:>6}  x{:<4.2}  {}{}{}{}",
+            row.score,
+            row.frecency,
+            visits,
+            format_age(row.last_visited, now),
+            row.proximity,
+            row.path,
+            via(row),
+            project,
+            marker
+        );
+    }
+}
+
+/// Print every stage of keyword resolution and the candidates it considered.
+/// Nothing is recorded and the undo stack is left untouched.
+pub fn explain(state: &AppState, keyword: &str, config: &Config) {
+    eprintln!("Query: {}", keyword);
+
+    if let Some(alias_name) = keyword.strip_prefix('!') {
+        eprintln!("[alias] !{}", alias_name);
+        match load_aliases()
+            .map
+            .get(alias_name)
+            .map(|path| rewrite::localize(path, config))
+        {
+            Some(path) if Path::new(&path).is_dir() => {
+                eprintln!("    -> {}", path);
+                eprintln!("Result: {}", path);
+            }
+            Some(path) => {
+                eprintln!("    -> {} [not a directory]", path);
+                eprintln!("Result: no match");
+            }
+            None => {
+                eprintln!("    alias not found");
+                eprintln!("Result: no match");
+            }
+        }
+        return;
+    }
+
+    let query = Query::parse(keyword, config);
+    eprintln!("Terms: {}", query);
+
+    eprintln!("[local] relative to current directory");
+    if let Some(path) = try_local_path(keyword) {
+        eprintln!("    -> {}", path.display());
+        eprintln!("Result: {}", path.display());
+        return;
+    }
+    eprintln!("    no such directory");
+
+    let proximity = Proximity::current();
+    eprintln!(
+        "[proximity] cwd {}, git root {} (weight x{}, project roots x{})",
+        proximity
+            .cwd()
+            .map_or("unknown".to_string(), |p| p.display().to_string()),
+        proximity
+            .git_root()
+            .map_or("none".to_string(), |p| p.display().to_string()),
+        config.proximity_weight,
+        1.0 + config.project_weight
+    );
+
+    let rows: Vec<Candidate> = rank::breakdown(state, &query, config).collect();
+    let selected = rows
+        .iter()
+        .find(|row| matches!(Status::of(row), Status::Ok));
+
+    for tier in [
+        MatchTier::Exact,
+        MatchTier::Partial,
+        MatchTier::Kana,
+        MatchTier::Ancestor,
+        MatchTier::Fuzzy,
+    ] {
+        let on = if tier == MatchTier::Ancestor {
+            "a parent directory"
+        } else {
+            "last directory name"
+        };
+        eprintln!(
+            "[history] {} match on {} (weight x{})",
+            tier.label(),
+            on,
+            tier.weight()
+        );
+        let tier_rows: Vec<&Candidate> = rows.iter().filter(|row| row.tier == tier).collect();
+        print_rows(&tier_rows, selected);
+    }
+
+    if let Some(row) = selected {
+        eprintln!("Result: {}", row.path);
+        return;
+    }
+
+    eprintln!(
+        "[index] directories below {} roots, scored by match tier and proximity",
+        config.index_roots.len()
+    );
+    let indexed = index::search(&query, config);
+    if indexed.is_empty() {
+        eprintln!("    (no candidates)");
+    } else {
+        eprintln!("    {:>9}  path", "score");
+    }
+    let chosen = indexed.iter().find(|c| Path::new(&c.path).is_dir());
+    for candidate in indexed.iter().take(typo::MAX_SUGGESTIONS) {
+        let marker = match chosen {
+            Some(c) if std::ptr::eq(c, candidate) => " <- selected",
+            _ if !Path::new(&candidate.path).is_dir() => " [not a directory]",
+            _ => "",
+        };
+        let project = if candidate.project { " [project]" } else { "" };
+        eprintln!(
+            "    {:>9.2}  {}{}{}",
+            candidate.score, candidate.path, project, marker
+        );
+    }
+    if indexed.len() > typo::MAX_SUGGESTIONS {
+        eprintln!("    ... {} more", indexed.len() - typo::MAX_SUGGESTIONS);
+    }
+    if let Some(candidate) = chosen {
+        eprintln!("Result: {}", candidate.path);
+        return;
+    }
+
+    eprintln!(
+        "[walk] below current directory (depth {}, {} entries)",
+        config.walk_max_depth, config.walk_max_entries
+    );
+    if let Some(cwd) = proximity.cwd() {
+        let walk = walk::search(cwd, &query, config);
+        let truncated = if walk.truncated { ", budget exhausted" } else { "" };
+        eprintln!("    read {} entries{}", walk.visited, truncated);
+        if let Some(path) = walk.found {
+            eprintln!("    -> {}", path.display());
+            eprintln!("Result: {}", path.display());
+            return;
+        }
+        eprintln!("    no match");
+    }
+
+    eprintln!("[typo] edit distance on directory names");
+    let suggestions = typo::suggest(state, &query, config);
+    if suggestions.is_empty() {
+        eprintln!("    (no candidates)");
+    } else {
+        eprintln!("    {:>9}  {:>6}  path", "score", "edits");
+    }
+    let winner = typo::clear_winner(&suggestions).map(|s| s.path.as_str());
+    for suggestion in suggestions.iter().take(typo::MAX_SUGGESTIONS) {
+        let marker = if winner == Some(suggestion.path.as_str()) {
+            " <- selected"
+        } else {
+            ""
+        };
+        eprintln!(
+            "    {:>9.2}  {:>6}  {}{}",
+            suggestion.score, suggestion.distance, suggestion.path, marker
+        );
+    }
+    match winner {
+        Some(path) => eprintln!("Result: {}", path),
+        None if !suggestions.is_empty() => eprintln!("Result: ambiguous, no jump"),
+        None => eprintln!("Result: no match"),
+    }
+}
@@ -0,0 +1,149 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::import::ZOXIDE_VERSION;
+use crate::{Aliases, AppState};
+
+/// Version of the JSON export layout, bumped on incompatible changes
+pub const EXPORT_VERSION: u32 = 1;
+
+/// Output formats of `j --export`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// History and aliases in a documented layout independent of state.json
+    Json,
+    /// One row per history entry or alias
+    Csv,
+    /// `path|rank|timestamp` lines, as read by z and fasd
+    Z,
+    /// zoxide's binary `db.zo`
+    Zoxide,
+}
+
+impl Format {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "json" => Some(Format::Json),
+            "csv" => Some(Format::Csv),
+            "z" => Some(Format::Z),
+            "zoxide" => Some(Format::Zoxide),
+            _ => None,
+        }
+    }
+
+    /// z and zoxide have no notion of aliases
+    pub fn has_aliases(self) -> bool {
+        matches!(self, Format::Json | Format::Csv)
+    }
+}
+
+#[derive(Serialize)]
+struct ExportedEntry<'a> {
+    path: &'a str,
+    visit_count: u32,
+    last_visited: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+struct Export<'a> {
+    version: u32,
+    history: Vec<ExportedEntry<'a>>,
+    /// Sorted by name so exports diff cleanly
+    aliases: BTreeMap<&'a str, &'a str>,
+}
+
+fn to_json(state: &AppState, aliases: &Aliases) -> Vec<u8> {
+    let export = Export {
+        version: EXPORT_VERSION,
+        history: state
+            .history
+            .iter()
+            .map(|entry| ExportedEntry {
+                path: &entry.path,
+                visit_count: entry.visit_count,
+                last_visited: entry.last_visited,
+            })
+            .collect(),
+        aliases: aliases
+            .map
+            .iter()
+            .map(|(name, path)| (name.as_str(), path.as_str()))
+            .collect(),
+    };
+    let mut out = serde_json::to_vec_pretty(&export).unwrap_or_default();
+    out.push(b'\n');
+    out
+}
+
+/// Quote a CSV field when it contains a separator, quote or line break
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// `kind,name,path,visit_count,last_visited`; aliases leave the last two empty
+/// and history entries the name
+fn to_csv(state: &AppState, aliases: &Aliases) -> Vec<u8> {
+    let mut out = String::from("kind,name,path,visit_count,last_visited\n");
+    for entry in &state.history {
+        out.push_str(&format!(
+            "history,,{},{},{}\n",
+            csv_field(&entry.path),
+            entry.visit_count,
+            entry.last_visited.to_rfc3339()
+        ));
+    }
+    let sorted: BTreeMap<&String, &String> = aliases.map.iter().collect();
+    for (name, path) in sorted {
+        out.push_str(&format!(
+            "alias,{},{},,\n",
+            csv_field(name),
+            csv_field(path)
+        ));
+    }
+    out.into_bytes()
+}
+
+/// Visit counts become ranks and the last visit the timestamp
+fn to_z(state: &AppState) -> Vec<u8> {
+    let mut out = String::new();
+    for entry in &state.history {
+        out.push_str(&format!(
+            "{}|{}|{}\n",
+            entry.path,
+            entry.visit_count,
+            entry.last_visited.timestamp()
+        ));
+    }
+    out.into_bytes()
+}
+
+/// zoxide's `db.zo` layout, which `j --import zoxide` reads back: version,
+/// entry count, then each path, rank and last access, all little-endian
+fn to_zoxide(state: &AppState) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(&ZOXIDE_VERSION.to_le_bytes());
+    out.extend_from_slice(&(state.history.len() as u64).to_le_bytes());
+    for entry in &state.history {
+        out.extend_from_slice(&(entry.path.len() as u64).to_le_bytes());
+        out.extend_from_slice(entry.path.as_bytes());
+        out.extend_from_slice(&(entry.visit_count as f64).to_le_bytes());
+        let last_accessed = entry.last_visited.timestamp().max(0) as u64;
+        out.extend_from_slice(&last_accessed.to_le_bytes());
+    }
+    out
+}
+
+/// History, and aliases where the format has them, in the given format
+pub fn render(format: Format, state: &AppState, aliases: &Aliases) -> Vec<u8> {
+    match format {
+        Format::Json => to_json(state, aliases),
+        Format::Csv => to_csv(state, aliases),
+        Format::Z => to_z(state),
+        Format::Zoxide => to_zoxide(state),
+    }
+}
@@ -0,0 +1,91 @@
+const SCORE_MATCH: i32 = 16;
+const SCORE_GAP_START: i32 = -3;
+const SCORE_GAP_EXTENSION: i32 = -1;
+
+/// Bonus for matching the first character of a word
+const BONUS_BOUNDARY: i32 = SCORE_MATCH / 2;
+/// Bonus for matching a camelCase hump or the first digit after a letter
+const BONUS_CAMEL: i32 = BONUS_BOUNDARY + SCORE_GAP_EXTENSION;
+/// Minimum bonus for every character of a consecutive run
+const BONUS_CONSECUTIVE: i32 = -(SCORE_GAP_START + SCORE_GAP_EXTENSION);
+/// The bonus of the first pattern character counts this many times
+const BONUS_FIRST_CHAR_MULTIPLIER: i32 = 2;
+
+fn is_separator(c: char) -> bool {
+    matches!(c, '-' | '_' | '.' | ' ' | '/' | '\\')
+}
+
+/// Bonus for matching `cur` given the character before it
+fn char_bonus(prev: Option<char>, cur: char) -> i32 {
+    if is_separator(cur) {
+        return 0;
+    }
+    match prev {
+        None => BONUS_BOUNDARY,
+        Some(p) if is_separator(p) => BONUS_BOUNDARY,
+        Some(p) if p.is_lowercase() && cur.is_uppercase() => BONUS_CAMEL,
+        Some(p) if !p.is_numeric() && cur.is_numeric() => BONUS_CAMEL,
+        _ => 0,
+    }
+}
+
+/// Score `pattern` as a subsequence of `text`, or `None` if it is not one.
+/// `pattern` must be lowercase; `text` keeps its case so camelCase humps count.
+/// Picks the best-scoring alignment, not just the leftmost one.
+pub fn score(pattern: &str, text: &str) -> Option<i32> {
+    let pattern: Vec<char> = pattern.chars().collect();
+    if pattern.is_empty() {
+        return None;
+    }
+    let chars: Vec<char> = text.chars().collect();
+    let lower: Vec<char> = chars
+        .iter()
+        .map(|c| c.to_lowercase().next().unwrap_or(*c))
+        .collect();
+    let bonus: Vec<i32> = (0..chars.len())
+        .map(|j| char_bonus(j.checked_sub(1).map(|k| chars[k]), chars[j]))
+        .collect();
+
+    // prev[j]: best score with the pattern so far matched and its last character at j,
+    // together with the bonus carried by the consecutive run ending there
+    let mut prev: Vec<Option<(i32, i32)>> = vec![None; chars.len()];
+    for (i, &pc) in pattern.iter().enumerate() {
+        let mut row: Vec<Option<(i32, i32)>> = vec![None; chars.len()];
+        // Best earlier score that leaves a gap before j, gap penalty included
+        let mut gapped: Option<i32> = None;
+        for j in 0..chars.len() {
+            if j >= 2 {
+                gapped = gapped
+                    .map(|s| s + SCORE_GAP_EXTENSION)
+                    .max(prev[j - 2].map(|(s, _)| s + SCORE_GAP_START));
+            }
+            if lower[j] != pc {
+                continue;
+            }
+            if i == 0 {
+                row[j] = Some((
+                    SCORE_MATCH + bonus[j] * BONUS_FIRST_CHAR_MULTIPLIER,
+                    bonus[j],
+                ));
+                continue;
+            }
+            let after_gap = gapped.map(|s| (s + SCORE_MATCH + bonus[j], bonus[j]));
+            let consecutive = j.checked_sub(1).and_then(|k| prev[k]).map(|(s, run)| {
+                let b = bonus[j].max(run).max(BONUS_CONSECUTIVE);
+                (s + SCORE_MATCH + b, b)
+            });
+            row[j] = after_gap.max(consecutive);
+        }
+        prev = row;
+    }
+    prev.into_iter().flatten().map(|(s, _)| s).max()
+}
+
+/// Match quality in (0, 1]: the score relative to a perfect match of the same length
+pub fn quality(pattern: &str, text: &str) -> Option<f64> {
+    let score = score(pattern, text)?;
+    let len = pattern.chars().count() as i32;
+    let best =
+        len * (SCORE_MATCH + BONUS_BOUNDARY) + BONUS_BOUNDARY * (BONUS_FIRST_CHAR_MULTIPLIER - 1);
+    Some((score.max(1) as f64 / best as f64).min(1.0))
+}
@@ -0,0 +1,302 @@
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::rewrite;
+use crate::storage::path_key;
+use crate::{AppState, Config, HistoryEntry};
+
+/// Databases of other directory jumpers that can be imported
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// `autojump.txt`: `weight<TAB>path` lines
+    Autojump,
+    /// `~/.z`: `path|rank|timestamp` lines
+    Z,
+    /// `~/.fasd`: the same layout as z, but also lists files
+    Fasd,
+    /// zoxide's binary `db.zo`
+    Zoxide,
+}
+
+impl Format {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "autojump" => Some(Format::Autojump),
+            "z" => Some(Format::Z),
+            "fasd" => Some(Format::Fasd),
+            "zoxide" => Some(Format::Zoxide),
+            _ => None,
+        }
+    }
+
+    /// Where the tool keeps its database unless told otherwise
+    pub fn default_path(self) -> Option<PathBuf> {
+        let env_path = |var: &str| {
+            env::var_os(var)
+                .filter(|v| !v.is_empty())
+                .map(PathBuf::from)
+        };
+        match self {
+            Format::Autojump => {
+                let dir = if cfg!(target_os = "macos") {
+                    dirs::home_dir()?.join("Library")
+                } else {
+                    dirs::data_dir()?
+                };
+                Some(dir.join("autojump").join("autojump.txt"))
+            }
+            Format::Z => env_path("_Z_DATA").or_else(|| Some(dirs::home_dir()?.join(".z"))),
+            Format::Fasd => {
+                env_path("_FASD_DATA").or_else(|| Some(dirs::home_dir()?.join(".fasd")))
+            }
+            Format::Zoxide => env_path("_ZO_DATA_DIR")
+                .or_else(|| Some(dirs::data_local_dir()?.join("zoxide")))
+                .map(|dir| dir.join("db.zo")),
+        }
+    }
+}
+
+/// One directory read from another tool's database
+pub struct Imported {
+    pub path: String,
+    pub visit_count: u32,
+    pub last_visited: DateTime<Utc>,
+}
+
+impl From<HistoryEntry> for Imported {
+    fn from(entry: HistoryEntry) -> Self {
+        Imported {
+            path: entry.path,
+            visit_count: entry.visit_count,
+            last_visited: entry.last_visited,
+        }
+    }
+}
+
+/// Outcome of merging imported entries into the history
+#[derive(Debug, Default)]
+pub struct Summary {
+    /// Entries in the database
+    pub read: usize,
+    /// Not in the history before
+    pub added: usize,
+    /// Combined with an existing history entry
+    pub merged: usize,
+    /// No longer a directory on disk
+    pub skipped: usize,
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} new, {} merged with existing history, {} skipped (not a directory)",
+            self.added, self.merged, self.skipped
+        )
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Scores are visits scaled down by aging, so round them back to a count
+fn count_from_rank(rank: f64) -> u32 {
+    if rank.is_finite() {
+        rank.round().clamp(1.0, u32::MAX as f64) as u32
+    } else {
+        1
+    }
+}
+
+fn from_epoch(secs: i64) -> DateTime<Utc> {
+    DateTime::from_timestamp(secs, 0).unwrap_or_else(Utc::now)
+}
+
+/// Drop trailing separators, which some tools keep
+fn clean_path(path: &str) -> String {
+    let trimmed = path.trim_end_matches(['/', '\\']);
+    if trimmed.is_empty() {
+        path.to_string()
+    } else {
+        trimmed.to_string()
+    }
+}
+
+/// autojump adds visits as `sqrt(weight² + 10²)`, so a weight of `w` stands
+/// for `(w / 10)²` visits. It keeps no timestamps, so the file's
+/// modification time is used for every entry.
+fn parse_autojump(contents: &str, modified: DateTime<Utc>) -> Vec<Imported> {
+    contents
+        .lines()
+        .filter_map(|line| {
+            let (weight, path) = line.split_once('\t')?;
+            let weight: f64 = weight.trim().parse().ok()?;
+            Some(Imported {
+                path: clean_path(path),
+                visit_count: count_from_rank((weight / 10.0).powi(2)),
+                last_visited: modified,
+            })
+        })
+        .collect()
+}
+
+/// z and fasd add 1 to the rank per visit and store the last visit in epoch seconds
+fn parse_z(contents: &str) -> Vec<Imported> {
+    contents
+        .lines()
+        .filter_map(|line| {
+            // The path itself may contain `|`
+            let mut fields = line.rsplitn(3, '|');
+            let timestamp: i64 = fields.next()?.trim().parse().ok()?;
+            let rank: f64 = fields.next()?.trim().parse().ok()?;
+            let path = fields.next()?;
+            Some(Imported {
+                path: clean_path(path),
+                visit_count: count_from_rank(rank),
+                last_visited: from_epoch(timestamp),
+            })
+        })
+        .collect()
+}
+
+/// Little-endian reader for the bincode layout zoxide writes
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> io::Result<&'a [u8]> {
+        if self.bytes.len() < n {
+            return Err(invalid("truncated zoxide database"));
+        }
+        let (head, rest) = self.bytes.split_at(n);
+        self.bytes = rest;
+        Ok(head)
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        let mut buf = [0; 4];
+        buf.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(buf))
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        let mut buf = [0; 8];
+        buf.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(buf))
+    }
+
+    fn f64(&mut self) -> io::Result<f64> {
+        self.u64().map(f64::from_bits)
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        let len = usize::try_from(self.u64()?).map_err(|_| invalid("path too long"))?;
+        let bytes = self.take(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| invalid("path is not UTF-8"))
+    }
+}
+
+/// Version of the zoxide database layout this reader understands
+pub const ZOXIDE_VERSION: u32 = 3;
+
+/// `db.zo`: a u32 version, then a u64 entry count and for each entry the
+/// path (u64 length and bytes), the rank (f64) and the last access (u64 epoch seconds)
+fn parse_zoxide(bytes: &[u8]) -> io::Result<Vec<Imported>> {
+    let mut reader = Reader { bytes };
+    let version = reader.u32()?;
+    if version != ZOXIDE_VERSION {
+        return Err(invalid(&format!(
+            "unsupported zoxide database version {}",
+            version
+        )));
+    }
+    let count = reader.u64()?;
+    let mut entries = Vec::new();
+    for _ in 0..count {
+        let path = reader.string()?;
+        let rank = reader.f64()?;
+        let last_accessed = reader.u64()?;
+        entries.push(Imported {
+            path: clean_path(&path),
+            visit_count: count_from_rank(rank),
+            last_visited: from_epoch(i64::try_from(last_accessed).unwrap_or(0)),
+        });
+    }
+    Ok(entries)
+}
+
+/// Read every entry of another tool's database
+pub fn read(format: Format, path: &Path) -> io::Result<Vec<Imported>> {
+    match format {
+        Format::Zoxide => parse_zoxide(&fs::read(path)?),
+        Format::Autojump => {
+            let modified = fs::metadata(path)?
+                .modified()
+                .map(DateTime::<Utc>::from)
+                .unwrap_or_else(|_| Utc::now());
+            Ok(parse_autojump(&fs::read_to_string(path)?, modified))
+        }
+        Format::Z | Format::Fasd => Ok(parse_z(&fs::read_to_string(path)?)),
+    }
+}
+
+/// Merge imported entries into the history. Paths are first mapped to this
+/// machine with `path_rewrites`. Paths already recorded (compared like
+/// `add_to_history` does) have the visit counts added and keep the later
+/// visit; new paths are inserted at the old end of the history so the most
+/// recent jump stays last. Entries that are no longer directories, including
+/// the files fasd tracks, are skipped.
+pub fn merge(state: &mut AppState, entries: Vec<Imported>, config: &Config) -> Summary {
+    let mut summary = Summary {
+        read: entries.len(),
+        ..Summary::default()
+    };
+    // Positions in the history, then past its end in `added`
+    let recorded = state.history.len();
+    let mut positions: HashMap<String, usize> = HashMap::with_capacity(recorded);
+    for (i, entry) in state.history.iter().enumerate() {
+        positions.entry(path_key(&entry.path)).or_insert(i);
+    }
+    let mut added: Vec<HistoryEntry> = Vec::new();
+    for mut entry in entries {
+        entry.path = rewrite::localize(&entry.path, config);
+        if !Path::new(&entry.path).is_dir() {
+            summary.skipped += 1;
+            continue;
+        }
+        let key = path_key(&entry.path);
+        let existing = match positions.get(&key) {
+            Some(&i) if i < recorded => Some(&mut state.history[i]),
+            Some(&i) => Some(&mut added[i - recorded]),
+            None => None,
+        };
+        match existing {
+            Some(existing) => {
+                existing.visit_count = existing.visit_count.saturating_add(entry.visit_count);
+                existing.last_visited = existing.last_visited.max(entry.last_visited);
+                existing.missing_since = None;
+                summary.merged += 1;
+            }
+            None => {
+                positions.insert(key, recorded + added.len());
+                added.push(HistoryEntry {
+                    path: entry.path,
+                    last_visited: entry.last_visited,
+                    visit_count: entry.visit_count,
+                    missing_since: None,
+                });
+                summary.added += 1;
+            }
+        }
+    }
+    added.sort_by_key(|entry| entry.last_visited);
+    state.history.splice(0..0, added);
+    summary
+}
@@ -0,0 +1,313 @@
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use crate::project;
+use crate::proximity::Proximity;
+use crate::query::Query;
+use crate::rank::{self, Candidate};
+use crate::storage::{get_index_path, json};
+use crate::{is_excluded, Config};
+
+/// First line of the index file
+const HEADER: &str = "j-index 2";
+/// Prefix of the header lines recording the exclude patterns in effect
+const EXCLUDE_PREFIX: &str = "exclude\t";
+/// Prefix of the header lines recording the project markers in effect
+const MARKER_PREFIX: &str = "marker\t";
+
+/// One indexed directory
+struct Entry {
+    path: String,
+    /// Modification time in nanoseconds since the epoch when the directory
+    /// was last listed; 0 if it could not be read
+    mtime: u64,
+    /// The listing contained a project marker
+    project: bool,
+}
+
+/// Every directory below the configured roots, sorted by path.
+///
+/// Stored next to state.json as text, front-coded to stay compact: after the
+/// header, each line holds the mtime, `p` for a project root or `-`, the
+/// number of bytes shared with the previous path, and the rest of the path,
+/// separated by tabs.
+#[derive(Default)]
+pub struct Index {
+    /// Exclude patterns the index was built with
+    excludes: Vec<String>,
+    /// Project markers the index was built with
+    markers: Vec<String>,
+    entries: Vec<Entry>,
+}
+
+/// Outcome of `update`
+pub struct Update {
+    /// Directories now in the index
+    pub directories: usize,
+    /// Directories listed again because they changed or were new
+    pub reread: usize,
+}
+
+/// Length of the longest common prefix ending on a character boundary
+fn shared_prefix_len(a: &str, b: &str) -> usize {
+    let mut len = a.bytes().zip(b.bytes()).take_while(|(x, y)| x == y).count();
+    while !b.is_char_boundary(len) {
+        len -= 1;
+    }
+    len
+}
+
+fn modified_nanos(meta: &fs::Metadata) -> u64 {
+    meta.modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |since| since.as_nanos() as u64)
+}
+
+/// Configured roots, dropping duplicates and roots inside another root
+fn distinct_roots(config: &Config) -> Vec<PathBuf> {
+    let roots: Vec<PathBuf> = config.index_roots.iter().map(PathBuf::from).collect();
+    let mut distinct: Vec<PathBuf> = Vec::new();
+    for (i, root) in roots.iter().enumerate() {
+        let covered = roots.iter().enumerate().any(|(j, other)| {
+            (other != root && root.starts_with(other)) || (other == root && j < i)
+        });
+        if !covered {
+            distinct.push(root.clone());
+        }
+    }
+    distinct
+}
+
+impl Index {
+    /// Read the index, or an empty one if it is missing or unreadable
+    pub fn load() -> Self {
+        get_index_path()
+            .ok()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|contents| Self::parse(&contents))
+            .unwrap_or_default()
+    }
+
+    fn parse(contents: &str) -> Option<Self> {
+        let mut lines = contents.lines();
+        if lines.next()? != HEADER {
+            return None;
+        }
+        let mut index = Index::default();
+        for line in lines {
+            if let Some(pattern) = line.strip_prefix(EXCLUDE_PREFIX) {
+                index.excludes.push(pattern.to_string());
+                continue;
+            }
+            if let Some(marker) = line.strip_prefix(MARKER_PREFIX) {
+                index.markers.push(marker.to_string());
+                continue;
+            }
+            let mut fields = line.splitn(4, '\t');
+            let mtime = fields.next()?.parse().ok()?;
+            let project = fields.next()? == "p";
+            let shared: usize = fields.next()?.parse().ok()?;
+            let suffix = fields.next()?;
+            let previous = index.entries.last().map_or("", |entry| entry.path.as_str());
+            let mut path = previous.get(..shared)?.to_string();
+            path.push_str(suffix);
+            index.entries.push(Entry {
+                path,
+                mtime,
+                project,
+            });
+        }
+        Some(index)
+    }
+
+    fn serialize(&self) -> String {
+        let mut out = String::new();
+        out.push_str(HEADER);
+        out.push('\n');
+        for pattern in &self.excludes {
+            let _ = writeln!(out, "{}{}", EXCLUDE_PREFIX, pattern);
+        }
+        for marker in &self.markers {
+            let _ = writeln!(out, "{}{}", MARKER_PREFIX, marker);
+        }
+        let mut previous = "";
+        for entry in &self.entries {
+            let shared = shared_prefix_len(previous, &entry.path);
+            let _ = writeln!(
+                out,
+                "{}\t{}\t{}\t{}",
+                entry.mtime,
+                if entry.project { "p" } else { "-" },
+                shared,
+                &entry.path[shared..]
+            );
+            previous = &entry.path;
+        }
+        out
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        json::write_atomic(&get_index_path()?, self.serialize().as_bytes())
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Drop directories no longer below any configured root
+    pub fn retain_roots(&mut self, config: &Config) {
+        let roots = distinct_roots(config);
+        self.entries.retain(|entry| {
+            roots
+                .iter()
+                .any(|root| Path::new(&entry.path).starts_with(root))
+        });
+    }
+}
+
+/// Walk every configured root and rewrite the index. Hidden and excluded
+/// directories are skipped and symlinks are not followed. A directory whose
+/// mtime is unchanged since the last update has the same children and
+/// markers, so its listing is taken from the old index and only new or
+/// changed directories are read again; every directory is still checked
+/// with a single stat.
+pub fn update(config: &Config) -> io::Result<Update> {
+    let old = Index::load();
+    // Children hidden by a pattern that has since been removed are missing
+    // from the old listings, so they are only reused under the same excludes
+    // and markers
+    let reuse = old.excludes == config.excludes && old.markers == config.project_markers;
+    let previous: HashMap<&str, &Entry> = old
+        .entries
+        .iter()
+        .map(|entry| (entry.path.as_str(), entry))
+        .collect();
+    let mut children: HashMap<&Path, Vec<&str>> = HashMap::new();
+    for entry in &old.entries {
+        if let Some(parent) = Path::new(&entry.path).parent() {
+            children.entry(parent).or_default().push(&entry.path);
+        }
+    }
+
+    let mut index = Index {
+        excludes: config.excludes.clone(),
+        markers: config.project_markers.clone(),
+        entries: Vec::new(),
+    };
+    let mut reread = 0;
+    let mut stack: Vec<PathBuf> = distinct_roots(config)
+        .into_iter()
+        .filter(|root| !is_excluded(&root.to_string_lossy(), config))
+        .collect();
+    while let Some(dir) = stack.pop() {
+        let meta = match fs::symlink_metadata(&dir) {
+            Ok(meta) if meta.is_dir() => meta,
+            _ => continue,
+        };
+        let path = dir.to_string_lossy().into_owned();
+        let mut mtime = modified_nanos(&meta);
+        let mut project = false;
+        let unchanged = previous
+            .get(path.as_str())
+            .filter(|entry| reuse && mtime != 0 && entry.mtime == mtime);
+        if let Some(entry) = unchanged {
+            project = entry.project;
+            if let Some(listed) = children.get(dir.as_path()) {
+                stack.extend(listed.iter().map(PathBuf::from));
+            }
+        } else {
+            reread += 1;
+            match fs::read_dir(&dir) {
+                Ok(entries) => {
+                    for entry in entries.flatten() {
+                        if project::is_marker(&entry.file_name().to_string_lossy(), config) {
+                            project = true;
+                        }
+                        if !entry.file_type().is_ok_and(|t| t.is_dir())
+                            || entry.file_name().to_string_lossy().starts_with('.')
+                        {
+                            continue;
+                        }
+                        let child = entry.path();
+                        let child_str = child.to_string_lossy();
+                        // A newline would break the line-based file format
+                        if child_str.contains('\n') || is_excluded(&child_str, config) {
+                            continue;
+                        }
+                        stack.push(child);
+                    }
+                }
+                // Read it again next time, in case it becomes readable
+                Err(_) => mtime = 0,
+            }
+        }
+        index.entries.push(Entry {
+            path,
+            mtime,
+            project,
+        });
+    }
+    index.entries.sort_by(|a, b| a.path.cmp(&b.path));
+    index.save()?;
+    Ok(Update {
+        directories: index.len(),
+        reread,
+    })
+}
+
+/// Indexed directories matching the query, best first. They have never been
+/// visited, so they score by match tier and proximity alone; callers list
+/// them after every history candidate. A query without terms matches nothing
+/// here, so listing everything does not dump the whole index.
+pub fn search(query: &Query, config: &Config) -> Vec<Candidate> {
+    if query.is_empty() || config.index_roots.is_empty() {
+        return Vec::new();
+    }
+    let roots = distinct_roots(config);
+    let proximity = Proximity::current();
+    let mut candidates: Vec<Candidate> = Index::load()
+        .entries
+        .into_iter()
+        .filter(|entry| {
+            roots
+                .iter()
+                .any(|root| Path::new(&entry.path).starts_with(root))
+        })
+        .filter(|entry| !is_excluded(&entry.path, config))
+        .filter_map(|entry| {
+            let m = rank::match_query(&entry.path, query, config)?;
+            // The parent an ancestor match resolves to is indexed itself
+            if m.resolved.is_some() {
+                return None;
+            }
+            let near = proximity.factor(&entry.path, config.proximity_weight);
+            Some(Candidate {
+                score: rank::weighted(1.0, &m) * near * project::factor(entry.project, config),
+                last_visited: DateTime::<Utc>::from_timestamp_nanos(entry.mtime as i64),
+                path: entry.path,
+                implicit: false,
+                project: entry.project,
+                tier: m.tier,
+                frecency: 1.0,
+                proximity: near,
+                visits: None,
+                via: None,
+                excluded: false,
+            })
+        })
+        .collect();
+    // Ties go to the shorter path, as in the walk below the current directory
+    candidates.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then_with(|| a.path.len().cmp(&b.path.len()))
+            .then_with(|| a.path.cmp(&b.path))
+    });
+    candidates
+}
@@ -0,0 +1,300 @@
+/// Hiragana and the romaji spellings that type them; the first spelling is
+/// the one used when transliterating. Two-kana sounds come first so the
+/// longest spelling wins.
+const TABLE: &[(&str, &[&str])] = &[
+    ("きゃ", &["kya"]),
+    ("きゅ", &["kyu"]),
+    ("きょ", &["kyo"]),
+    ("ぎゃ", &["gya"]),
+    ("ぎゅ", &["gyu"]),
+    ("ぎょ", &["gyo"]),
+    ("しゃ", &["sha", "sya"]),
+    ("しゅ", &["shu", "syu"]),
+    ("しぇ", &["she", "sye"]),
+    ("しょ", &["sho", "syo"]),
+    ("じゃ", &["ja", "zya", "jya"]),
+    ("じゅ", &["ju", "zyu", "jyu"]),
+    ("じぇ", &["je", "zye", "jye"]),
+    ("じょ", &["jo", "zyo", "jyo"]),
+    ("ちゃ", &["cha", "tya", "cya"]),
+    ("ちゅ", &["chu", "tyu", "cyu"]),
+    ("ちぇ", &["che", "tye", "cye"]),
+    ("ちょ", &["cho", "tyo", "cyo"]),
+    ("にゃ", &["nya"]),
+    ("にゅ", &["nyu"]),
+    ("にょ", &["nyo"]),
+    ("ひゃ", &["hya"]),
+    ("ひゅ", &["hyu"]),
+    ("ひょ", &["hyo"]),
+    ("びゃ", &["bya"]),
+    ("びゅ", &["byu"]),
+    ("びょ", &["byo"]),
+    ("ぴゃ", &["pya"]),
+    ("ぴゅ", &["pyu"]),
+    ("ぴょ", &["pyo"]),
+    ("みゃ", &["mya"]),
+    ("みゅ", &["myu"]),
+    ("みょ", &["myo"]),
+    ("りゃ", &["rya"]),
+    ("りゅ", &["ryu"]),
+    ("りょ", &["ryo"]),
+    ("ふぁ", &["fa"]),
+    ("ふぃ", &["fi"]),
+    ("ふぇ", &["fe"]),
+    ("ふぉ", &["fo"]),
+    ("てぃ", &["thi"]),
+    ("でぃ", &["dhi"]),
+    ("とぅ", &["twu"]),
+    ("どぅ", &["dwu"]),
+    ("うぃ", &["wi"]),
+    ("うぇ", &["we"]),
+    ("ゔぁ", &["va"]),
+    ("ゔぃ", &["vi"]),
+    ("ゔぇ", &["ve"]),
+    ("ゔぉ", &["vo"]),
+    ("つぁ", &["tsa"]),
+    ("あ", &["a"]),
+    ("い", &["i"]),
+    ("う", &["u"]),
+    ("え", &["e"]),
+    ("お", &["o"]),
+    ("か", &["ka", "ca"]),
+    ("き", &["ki"]),
+    ("く", &["ku", "cu", "qu"]),
+    ("け", &["ke"]),
+    ("こ", &["ko", "co"]),
+    ("が", &["ga"]),
+    ("ぎ", &["gi"]),
+    ("ぐ", &["gu"]),
+    ("げ", &["ge"]),
+    ("ご", &["go"]),
+    ("さ", &["sa"]),
+    ("し", &["shi", "si", "ci"]),
+    ("す", &["su"]),
+    ("せ", &["se", "ce"]),
+    ("そ", &["so"]),
+    ("ざ", &["za"]),
+    ("じ", &["ji", "zi"]),
+    ("ず", &["zu"]),
+    ("ぜ", &["ze"]),
+    ("ぞ", &["zo"]),
+    ("た", &["ta"]),
+    ("ち", &["chi", "ti"]),
+    ("つ", &["tsu", "tu"]),
+    ("て", &["te"]),
+    ("と", &["to"]),
+    ("だ", &["da"]),
+    ("ぢ", &["di"]),
+    ("づ", &["du"]),
+    ("で", &["de"]),
+    ("ど", &["do"]),
+    ("な", &["na"]),
+    ("に", &["ni"]),
+    ("ぬ", &["nu"]),
+    ("ね", &["ne"]),
+    ("の", &["no"]),
+    ("は", &["ha"]),
+    ("ひ", &["hi"]),
+    ("ふ", &["fu", "hu"]),
+    ("へ", &["he"]),
+    ("ほ", &["ho"]),
+    ("ば", &["ba"]),
+    ("び", &["bi"]),
+    ("ぶ", &["bu"]),
+    ("べ", &["be"]),
+    ("ぼ", &["bo"]),
+    ("ぱ", &["pa"]),
+    ("ぴ", &["pi"]),
+    ("ぷ", &["pu"]),
+    ("ぺ", &["pe"]),
+    ("ぽ", &["po"]),
+    ("ま", &["ma"]),
+    ("み", &["mi"]),
+    ("む", &["mu"]),
+    ("め", &["me"]),
+    ("も", &["mo"]),
+    ("や", &["ya"]),
+    ("ゆ", &["yu"]),
+    ("よ", &["yo"]),
+    ("ら", &["ra"]),
+    ("り", &["ri"]),
+    ("る", &["ru"]),
+    ("れ", &["re"]),
+    ("ろ", &["ro"]),
+    ("わ", &["wa"]),
+    ("を", &["wo"]),
+    ("ゔ", &["vu"]),
+    ("ぁ", &["xa", "la"]),
+    ("ぃ", &["xi", "li"]),
+    ("ぅ", &["xu", "lu"]),
+    ("ぇ", &["xe", "le"]),
+    ("ぉ", &["xo", "lo"]),
+    ("ゃ", &["xya", "lya"]),
+    ("ゅ", &["xyu", "lyu"]),
+    ("ょ", &["xyo", "lyo"]),
+    ("ゎ", &["xwa", "lwa"]),
+];
+
+const SMALL_TSU: char = 'っ';
+const N: char = 'ん';
+const LONG_VOWEL: char = 'ー';
+
+fn is_katakana(c: char) -> bool {
+    ('\u{30a1}'..='\u{30f6}').contains(&c)
+}
+
+fn is_hiragana(c: char) -> bool {
+    ('\u{3041}'..='\u{3096}').contains(&c)
+}
+
+/// Whether a string contains any kana worth transliterating
+pub fn has_kana(s: &str) -> bool {
+    s.chars().any(|c| is_hiragana(c) || is_katakana(c))
+}
+
+/// Fold katakana to hiragana, leaving everything else alone
+pub fn to_hiragana(s: &str) -> String {
+    s.chars()
+        .map(|c| {
+            if is_katakana(c) {
+                char::from_u32(c as u32 - 0x60).unwrap_or(c)
+            } else {
+                c
+            }
+        })
+        .collect()
+}
+
+/// Transliterate hiragana to romaji (Hepburn-style); other characters pass through.
+/// `っ` doubles the next consonant and `ー` repeats the previous vowel.
+pub fn to_romaji(s: &str) -> String {
+    let chars: Vec<char> = s.chars().collect();
+    let mut out = String::new();
+    let mut double = false;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c == SMALL_TSU {
+            double = true;
+            i += 1;
+            continue;
+        }
+        if c == N {
+            out.push('n');
+        } else if c == LONG_VOWEL {
+            if let Some(vowel) = out.chars().last().filter(|v| "aiueo".contains(*v)) {
+                out.push(vowel);
+            }
+        } else {
+            let pair: String = chars[i..chars.len().min(i + 2)].iter().collect();
+            let found = TABLE
+                .iter()
+                .find(|(kana, _)| pair.starts_with(kana))
+                .map(|(kana, spellings)| (kana.chars().count(), spellings[0]));
+            match found {
+                Some((len, romaji)) => {
+                    if double {
+                        let first = romaji.chars().next().unwrap_or('t');
+                        out.push(if first == 'c' { 't' } else { first });
+                    }
+                    out.push_str(romaji);
+                    double = false;
+                    i += len;
+                    continue;
+                }
+                None => out.push(c),
+            }
+        }
+        double = false;
+        i += 1;
+    }
+    out
+}
+
+/// Convert typed romaji to hiragana the way an IME would;
+/// anything that is not romaji passes through
+pub fn from_romaji(s: &str) -> String {
+    let chars: Vec<char> = s.chars().collect();
+    let is_vowel = |c: char| "aiueo".contains(c);
+    let mut out = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        if c == '-' {
+            out.push(LONG_VOWEL);
+            i += 1;
+            continue;
+        }
+        if c == 'n' {
+            if next == Some('n') || next == Some('\'') {
+                out.push(N);
+                i += 2;
+                continue;
+            }
+            if !next.is_some_and(|n| is_vowel(n) || n == 'y') {
+                out.push(N);
+                i += 1;
+                continue;
+            }
+        }
+        if c.is_ascii_lowercase()
+            && !is_vowel(c)
+            && (next == Some(c) || (c == 't' && next == Some('c')))
+        {
+            out.push(SMALL_TSU);
+            i += 1;
+            continue;
+        }
+        let found = (1..=3).rev().find_map(|len| {
+            let end = i + len;
+            if end > chars.len() {
+                return None;
+            }
+            let typed: String = chars[i..end].iter().collect();
+            TABLE
+                .iter()
+                .find(|(_, spellings)| spellings.contains(&typed.as_str()))
+                .map(|(kana, _)| (len, *kana))
+        });
+        match found {
+            Some((len, kana)) => {
+                out.push_str(kana);
+                i += len;
+            }
+            None => {
+                out.push(c);
+                i += 1;
+            }
+        }
+    }
+    out
+}
+
+/// A token or path component in the forms compared by kana matching
+pub struct KanaForms {
+    hiragana: String,
+    romaji: String,
+}
+
+impl KanaForms {
+    /// Forms of a lowercase query token, typed in romaji or kana.
+    /// Romaji is canonicalized through kana so `si` and `shi` compare equal.
+    pub fn of_token(token: &str) -> Self {
+        let hiragana = to_hiragana(token);
+        let romaji = to_romaji(&from_romaji(&hiragana));
+        KanaForms { hiragana, romaji }
+    }
+
+    /// Forms of a lowercase path component
+    pub fn of_name(name: &str) -> Self {
+        let hiragana = to_hiragana(name);
+        let romaji = to_romaji(&hiragana);
+        KanaForms { hiragana, romaji }
+    }
+
+    /// Whether `token` is contained in this name in either form
+    pub fn contains(&self, token: &KanaForms) -> bool {
+        self.hiragana.contains(&token.hiragana) || self.romaji.contains(&token.romaji)
+    }
+}
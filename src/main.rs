@@ -1,12 +1,36 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use std::collections::VecDeque;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::env;
-use std::fs;
-use std::io::{self, Write};
+use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 
+mod aging;
+mod doctor;
+mod export;
+mod explain;
+mod fuzzy;
+mod import;
+mod index;
+mod kana;
+mod merge;
+mod normalize;
+mod project;
+mod proximity;
+mod query;
+mod rank;
+mod rewrite;
+mod schema;
+mod shell_history;
+mod storage;
+mod typo;
+mod walk;
+
+use query::Query;
+use storage::{load_aliases, load_config, load_state, save_aliases, save_config, save_state};
+
 #[cfg(windows)]
 use winapi::um::wincon::{SetConsoleCP, SetConsoleOutputCP};
 #[cfg(windows)]
@@ -28,122 +52,241 @@ const PATH_SEP: char = '\\';
 #[cfg(not(windows))]
 const PATH_SEP: char = '/';
 
-const MAX_HISTORY: usize = 1000;
-const MAX_UNDO_STACK: usize = 50;
+const DEFAULT_MAX_HISTORY: usize = 1000;
+const DEFAULT_MAX_UNDO: usize = 50;
+const DEFAULT_DECAY_HALF_LIFE_DAYS: f64 = 30.0;
+/// Default `aging_threshold` per entry allowed by `max_history`
+const DEFAULT_AGING_VISITS_PER_ENTRY: u64 = 10;
+const DEFAULT_AGING_MIN_SCORE: f64 = 1.0;
+const DEFAULT_MISSING_RETENTION_DAYS: u32 = 90;
+const DEFAULT_AGING_BUDGET_MS: u64 = 20;
+const DEFAULT_PROXIMITY_WEIGHT: f64 = 1.0;
+const DEFAULT_WALK_MAX_DEPTH: usize = 4;
+const DEFAULT_WALK_MAX_ENTRIES: usize = 5000;
+const DEFAULT_PROJECT_WEIGHT: f64 = 1.0;
+const DEFAULT_PROJECT_MARKERS: &[&str] = &[".git", "Cargo.toml", "package.json", "go.mod"];
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct HistoryEntry {
     path: String,
     last_visited: DateTime<Utc>,
     visit_count: u32,
+    /// When the aging pass first found the directory missing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    missing_since: Option<DateTime<Utc>>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize)]
 struct AppState {
     history: Vec<HistoryEntry>,
     undo_stack: VecDeque<String>,
     redo_stack: VecDeque<String>,
     current_dir: Option<String>,
+    /// History as last read from disk, used to merge concurrent updates on save
+    #[serde(skip)]
+    loaded_history: Vec<HistoryEntry>,
+    /// Entries aging has dropped so far, for `j --doctor`
+    #[serde(default, skip_serializing_if = "aging::Dropped::is_empty")]
+    aged_out: aging::Dropped,
+    /// History other machines published to the sync directory. Used for
+    /// ranking only; it is never trimmed, aged or saved.
+    #[serde(skip)]
+    synced: Vec<HistoryEntry>,
 }
 
-impl Default for AppState {
-    fn default() -> Self {
-        Self {
-            history: Vec::new(),
-            undo_stack: VecDeque::new(),
-            redo_stack: VecDeque::new(),
-            current_dir: None,
+impl AppState {
+    /// History to rank: this machine's entries plus the synced ones, with
+    /// the visit counts of paths recorded on several machines summed
+    fn ranked_history(&self) -> Cow<'_, [HistoryEntry]> {
+        if self.synced.is_empty() {
+            return Cow::Borrowed(&self.history);
         }
+        let mut combined = self.history.clone();
+        let mut index: HashMap<String, usize> = combined
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| (entry.path.to_ascii_lowercase(), i))
+            .collect();
+        for entry in &self.synced {
+            match index.get(&entry.path.to_ascii_lowercase()) {
+                Some(&i) => {
+                    let existing = &mut combined[i];
+                    existing.visit_count = existing.visit_count.saturating_add(entry.visit_count);
+                    existing.last_visited = existing.last_visited.max(entry.last_visited);
+                }
+                None => {
+                    index.insert(entry.path.to_ascii_lowercase(), combined.len());
+                    combined.push(entry.clone());
+                }
+            }
+        }
+        Cow::Owned(combined)
     }
 }
 
 #[derive(Debug, Default, Serialize, Deserialize)]
 struct Aliases {
-    #[serde(flatten)]
+    #[serde(default, rename = "aliases")]
     map: std::collections::HashMap<String, String>,
 }
 
-#[derive(Debug, Default, Serialize, Deserialize)]
-struct Config {
-    #[serde(default)]
-    excludes: Vec<String>,
-}
-
-fn get_config_dir() -> PathBuf {
-    let config_dir = dirs::home_dir()
-        .unwrap_or_else(|| PathBuf::from("."))
-        .join(".config")
-        .join("j");
-    fs::create_dir_all(&config_dir).ok();
-    config_dir
-}
-
-fn get_state_path() -> PathBuf {
-    get_config_dir().join("state.json")
-}
-
-fn get_aliases_path() -> PathBuf {
-    get_config_dir().join("aliases.json")
+/// Which history entries are dropped first once the history cap is reached
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum EvictionPolicy {
+    /// Least recently visited
+    Lru,
+    /// Least frequently visited
+    Lfu,
+    /// Lowest visit count after exponential decay by age
+    #[default]
+    Frecency,
 }
 
-fn get_config_path() -> PathBuf {
-    get_config_dir().join("config.json")
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+struct Config {
+    excludes: Vec<String>,
+    storage: storage::StorageKind,
+    /// Maximum number of history entries kept
+    max_history: usize,
+    /// Maximum depth of the undo and redo stacks
+    max_undo: usize,
+    /// Days after which a visit counts half as much, for ranking and frecency eviction
+    decay_half_life_days: f64,
+    eviction: EvictionPolicy,
+    /// Total visit count above which all counts are scaled down (0 disables
+    /// aging). Unset means `DEFAULT_AGING_VISITS_PER_ENTRY` per `max_history`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    aging_threshold: Option<u64>,
+    /// Entries whose scaled visit count falls below this are dropped when aging
+    aging_min_score: f64,
+    /// Days a directory may be missing before its entry is dropped (0 keeps it)
+    missing_retention_days: u32,
+    /// Time budget for existence checks during one aging pass
+    aging_budget_ms: u64,
+    /// Let unaccented query letters match accented ones (`e` matches `é`)
+    fold_diacritics: bool,
+    /// Also match kana directory names against romaji and hiragana/katakana queries
+    kana_matching: bool,
+    /// How strongly candidates near the current directory and in the current
+    /// git repository are favoured (0 disables)
+    proximity_weight: f64,
+    /// How many levels below the current directory the fallback search descends
+    walk_max_depth: usize,
+    /// Directory entries the fallback search may read (0 disables it)
+    walk_max_entries: usize,
+    /// Directories whose whole tree is indexed by `j --index update`
+    index_roots: Vec<String>,
+    /// File or directory names that mark a project root
+    project_markers: Vec<String>,
+    /// How strongly project roots are favoured (0 disables)
+    project_weight: f64,
+    /// Shared folder where each machine publishes `state.<hostname>.json` and
+    /// reads the history of the others
+    sync_dir: Option<String>,
+    /// Groups of path prefixes naming the same place on different machines,
+    /// such as `/home/me/work` and `/Users/me/work`
+    path_rewrites: Vec<Vec<String>>,
+    /// Store alias targets under the home directory as `~/...`
+    home_relative_aliases: bool,
 }
 
-fn load_state() -> AppState {
-    let path = get_state_path();
-    if path.exists() {
-        fs::read_to_string(&path)
-            .ok()
-            .and_then(|s| serde_json::from_str(&s).ok())
-            .unwrap_or_default()
-    } else {
-        AppState::default()
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            excludes: Vec::new(),
+            storage: storage::StorageKind::default(),
+            max_history: DEFAULT_MAX_HISTORY,
+            max_undo: DEFAULT_MAX_UNDO,
+            decay_half_life_days: DEFAULT_DECAY_HALF_LIFE_DAYS,
+            eviction: EvictionPolicy::default(),
+            aging_threshold: None,
+            aging_min_score: DEFAULT_AGING_MIN_SCORE,
+            missing_retention_days: DEFAULT_MISSING_RETENTION_DAYS,
+            aging_budget_ms: DEFAULT_AGING_BUDGET_MS,
+            fold_diacritics: false,
+            kana_matching: false,
+            proximity_weight: DEFAULT_PROXIMITY_WEIGHT,
+            walk_max_depth: DEFAULT_WALK_MAX_DEPTH,
+            walk_max_entries: DEFAULT_WALK_MAX_ENTRIES,
+            index_roots: Vec::new(),
+            project_markers: DEFAULT_PROJECT_MARKERS.iter().map(|m| m.to_string()).collect(),
+            project_weight: DEFAULT_PROJECT_WEIGHT,
+            sync_dir: None,
+            path_rewrites: Vec::new(),
+            home_relative_aliases: false,
+        }
     }
 }
 
-fn save_state(state: &AppState) -> io::Result<()> {
-    let path = get_state_path();
-    let json = serde_json::to_string_pretty(state)?;
-    fs::write(path, json)
-}
-
-fn load_aliases() -> Aliases {
-    let path = get_aliases_path();
-    if path.exists() {
-        fs::read_to_string(&path)
-            .ok()
-            .and_then(|s| serde_json::from_str(&s).ok())
-            .unwrap_or_default()
-    } else {
-        Aliases::default()
+impl Config {
+    /// The configured `aging_threshold`, or the default for `max_history`
+    fn aging_threshold(&self) -> u64 {
+        self.aging_threshold
+            .unwrap_or(self.max_history as u64 * DEFAULT_AGING_VISITS_PER_ENTRY)
     }
-}
-
-fn save_aliases(aliases: &Aliases) -> io::Result<()> {
-    let path = get_aliases_path();
-    let json = serde_json::to_string_pretty(aliases)?;
-    fs::write(path, json)
-}
 
-fn load_config() -> Config {
-    let path = get_config_path();
-    if path.exists() {
-        fs::read_to_string(&path)
-            .ok()
-            .and_then(|s| serde_json::from_str(&s).ok())
-            .unwrap_or_default()
-    } else {
-        Config::default()
+    /// Reset out-of-range settings to their defaults, describing each fix
+    fn sanitize(&mut self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.max_history == 0 {
+            problems.push(format!(
+                "max_history must be at least 1; using {}",
+                DEFAULT_MAX_HISTORY
+            ));
+            self.max_history = DEFAULT_MAX_HISTORY;
+        }
+        if let Some(threshold) = self.aging_threshold {
+            if threshold != 0 && threshold < self.max_history as u64 {
+                self.aging_threshold = None;
+                problems.push(format!(
+                    "aging_threshold ({}) is below max_history ({}), so aging would drop every \
+                     directory visited once; using {}",
+                    threshold,
+                    self.max_history,
+                    self.aging_threshold()
+                ));
+            }
+        }
+        if !(self.decay_half_life_days.is_finite() && self.decay_half_life_days > 0.0) {
+            problems.push(format!(
+                "decay_half_life_days must be a positive number; using {}",
+                DEFAULT_DECAY_HALF_LIFE_DAYS
+            ));
+            self.decay_half_life_days = DEFAULT_DECAY_HALF_LIFE_DAYS;
+        }
+        if !(self.aging_min_score.is_finite() && self.aging_min_score >= 0.0) {
+            problems.push(format!(
+                "aging_min_score must be zero or positive; using {}",
+                DEFAULT_AGING_MIN_SCORE
+            ));
+            self.aging_min_score = DEFAULT_AGING_MIN_SCORE;
+        }
+        if !(self.proximity_weight.is_finite() && self.proximity_weight >= 0.0) {
+            problems.push(format!(
+                "proximity_weight must be zero or positive; using {}",
+                DEFAULT_PROXIMITY_WEIGHT
+            ));
+            self.proximity_weight = DEFAULT_PROXIMITY_WEIGHT;
+        }
+        if !(self.project_weight.is_finite() && self.project_weight >= 0.0) {
+            problems.push(format!(
+                "project_weight must be zero or positive; using {}",
+                DEFAULT_PROJECT_WEIGHT
+            ));
+            self.project_weight = DEFAULT_PROJECT_WEIGHT;
+        }
+        if self.path_rewrites.iter().any(|group| group.len() < 2) {
+            problems.push(
+                "path_rewrites groups need at least two prefixes; ignoring shorter groups".to_string(),
+            );
+            self.path_rewrites.retain(|group| group.len() >= 2);
+        }
+        problems
     }
 }
 
-fn save_config(config: &Config) -> io::Result<()> {
-    let path = get_config_path();
-    let json = serde_json::to_string_pretty(config)?;
-    fs::write(path, json)
-}
-
 /// Check if a path matches any exclude pattern
 fn is_excluded(path: &str, config: &Config) -> bool {
     let path_lower = path.to_lowercase();
@@ -216,79 +359,31 @@ fn run_fzf(candidates: &[String], query: &str) -> Option<String> {
     None
 }
 
-/// Search history and return all matching candidates
-fn search_history_all(state: &AppState, keyword: &str, config: &Config) -> Vec<String> {
-    let tokens: Vec<String> = split_path(keyword)
-        .iter()
-        .map(|s| s.to_lowercase())
+/// Search history and return all matching candidates, best first,
+/// followed by matching directories from the index. With `projects_only`,
+/// only project roots are returned.
+fn search_history_all(
+    state: &AppState,
+    keyword: &str,
+    projects_only: bool,
+    config: &Config,
+) -> Vec<String> {
+    let query = Query::parse(keyword, config);
+    let mut paths: Vec<String> = rank::rank_candidates(state, &query, config)
+        .filter(|c| c.project || !projects_only)
+        .filter(|c| Path::new(&c.path).is_dir())
+        .map(|c| c.path)
         .collect();
-    
-    let mut results = Vec::new();
-    
-    if tokens.is_empty() {
-        // Return all valid directories
-        for entry in state.history.iter().rev() {
-            if !is_excluded(&entry.path, config) && Path::new(&entry.path).is_dir() {
-                results.push(entry.path.clone());
-            }
-        }
-        return results;
-    }
-    
-    let last_token = tokens.last().unwrap();
-    
-    // Exact match on last directory name
-    for entry in state.history.iter().rev() {
-        if is_excluded(&entry.path, config) {
-            continue;
-        }
-        let path = Path::new(&entry.path);
-        if let Some(dir_name) = path.file_name().and_then(|n| n.to_str()) {
-            if dir_name.to_lowercase() == *last_token {
-                if tokens.len() > 1 {
-                    let path_lower = entry.path.to_lowercase();
-                    let path_parts: Vec<&str> = split_path(&path_lower);
-                    let other_tokens = &tokens[..tokens.len()-1];
-                    if tokens_match_in_order(&path_parts[..path_parts.len().saturating_sub(1)], &other_tokens.to_vec()) 
-                        && Path::new(&entry.path).is_dir() 
-                    {
-                        results.push(entry.path.clone());
-                    }
-                } else if Path::new(&entry.path).is_dir() {
-                    results.push(entry.path.clone());
-                }
-            }
-        }
-    }
-    
-    // Partial match on last directory name
-    for entry in state.history.iter().rev() {
-        if is_excluded(&entry.path, config) {
-            continue;
-        }
-        if results.contains(&entry.path) {
-            continue;
-        }
-        let path = Path::new(&entry.path);
-        if let Some(dir_name) = path.file_name().and_then(|n| n.to_str()) {
-            if dir_name.to_lowercase().contains(last_token.as_str()) {
-                if tokens.len() > 1 {
-                    let path_lower = entry.path.to_lowercase();
-                    let path_parts: Vec<&str> = split_path(&path_lower);
-                    let other_tokens = &tokens[..tokens.len()-1];
-                    if tokens_match_in_order(&path_parts[..path_parts.len().saturating_sub(1)], &other_tokens.to_vec()) 
-                        && Path::new(&entry.path).is_dir() 
-                    {
-                        results.push(entry.path.clone());
-                    }
-                } else if Path::new(&entry.path).is_dir() {
-                    results.push(entry.path.clone());
-                }
-            }
-        }
-    }
-    
-    results
+    let seen: HashSet<String> = paths.iter().map(|p| p.to_ascii_lowercase()).collect();
+    paths.extend(
+        index::search(&query, config)
+            .into_iter()
+            .filter(|c| c.project || !projects_only)
+            .filter(|c| !seen.contains(&c.path.to_ascii_lowercase()))
+            .filter(|c| Path::new(&c.path).is_dir())
+            .map(|c| c.path),
+    );
+    paths
 }
 
 fn normalize_path_separator(path: &str) -> String {
@@ -302,10 +397,17 @@ fn normalize_path_separator(path: &str) -> String {
     }
 }
 
+/// Components of a stored path
 fn split_path(path: &str) -> Vec<&str> {
     path.split(&['/', '\\'][..]).filter(|s| !s.is_empty()).collect()
 }
 
+/// Components of typed input. Full-width slashes, as typed with an IME,
+/// separate components here; in a stored path they are part of a name.
+fn split_query(keyword: &str) -> Vec<&str> {
+    keyword.split(&['/', '\\', '／', '＼'][..]).filter(|s| !s.is_empty()).collect()
+}
+
 #[cfg(windows)]
 fn is_absolute_path(path: &str) -> bool {
     let normalized = normalize_path_separator(path);
@@ -360,7 +462,7 @@ fn expand_home(path: &str) -> Option<PathBuf> {
 
 fn try_local_path(keyword: &str) -> Option<PathBuf> {
     let current = env::current_dir().ok()?;
-    let tokens = split_path(keyword);
+    let tokens = split_query(keyword);
     
     if tokens.is_empty() {
         return None;
@@ -381,7 +483,11 @@ fn try_local_path(keyword: &str) -> Option<PathBuf> {
 /// Check if tokens appear in order within path parts
 /// e.g., tokens ["first", "one"] matches path "/home/first/project/one"
 /// but not "/home/one/project/first" (wrong order)
-fn tokens_match_in_order(path_parts: &[&str], tokens: &[String]) -> bool {
+fn tokens_match_in_order<T>(
+    path_parts: &[&str],
+    tokens: &[T],
+    matches: impl Fn(&T, &str) -> bool,
+) -> bool {
     if tokens.is_empty() {
         return true;
     }
@@ -394,7 +500,7 @@ fn tokens_match_in_order(path_parts: &[&str], tokens: &[String]) -> bool {
         
         let found = path_parts[start_index..]
             .iter()
-            .position(|part| part.contains(token.as_str()))
+            .position(|part| matches(token, part))
             .map(|pos| pos + start_index);
         
         match found {
@@ -410,105 +516,121 @@ fn tokens_match_in_order(path_parts: &[&str], tokens: &[String]) -> bool {
     true
 }
 
-fn search_history(state: &AppState, keyword: &str, config: &Config) -> Option<String> {
-    let tokens: Vec<String> = split_path(keyword)
-        .iter()
-        .map(|s| s.to_lowercase())
-        .collect();
-    
-    if tokens.is_empty() {
+/// Return the best-scoring existing directory matching the keyword,
+/// falling back to the index when no history entry matches.
+/// With `projects_only`, only project roots are considered.
+fn search_history(
+    state: &AppState,
+    keyword: &str,
+    projects_only: bool,
+    config: &Config,
+) -> Option<String> {
+    let query = Query::parse(keyword, config);
+    if query.is_empty() {
         return None;
     }
-    
-    let last_token = tokens.last().unwrap();
-    
-    // First pass: exact match on last directory name, with order-aware matching
-    for entry in state.history.iter().rev() {
-        if is_excluded(&entry.path, config) {
-            continue;
-        }
-        let path = Path::new(&entry.path);
-        if let Some(dir_name) = path.file_name().and_then(|n| n.to_str()) {
-            if dir_name.to_lowercase() == *last_token {
-                if tokens.len() > 1 {
-                    let path_lower = entry.path.to_lowercase();
-                    let path_parts: Vec<&str> = split_path(&path_lower);
-                    let other_tokens = &tokens[..tokens.len()-1];
-                    
-                    // Check if other tokens appear in order before the last token
-                    if tokens_match_in_order(&path_parts[..path_parts.len().saturating_sub(1)], &other_tokens.to_vec()) 
-                        && Path::new(&entry.path).is_dir() 
-                    {
-                        return Some(entry.path.clone());
-                    }
-                } else if Path::new(&entry.path).is_dir() {
-                    return Some(entry.path.clone());
-                }
-            }
-        }
-    }
-    
-    // Second pass: partial match on last directory name, with order-aware matching
-    for entry in state.history.iter().rev() {
-        if is_excluded(&entry.path, config) {
-            continue;
-        }
-        let path = Path::new(&entry.path);
-        if let Some(dir_name) = path.file_name().and_then(|n| n.to_str()) {
-            if dir_name.to_lowercase().contains(last_token.as_str()) {
-                if tokens.len() > 1 {
-                    let path_lower = entry.path.to_lowercase();
-                    let path_parts: Vec<&str> = split_path(&path_lower);
-                    let other_tokens = &tokens[..tokens.len()-1];
-                    
-                    // Check if other tokens appear in order before the last token
-                    if tokens_match_in_order(&path_parts[..path_parts.len().saturating_sub(1)], &other_tokens.to_vec()) 
-                        && Path::new(&entry.path).is_dir() 
-                    {
-                        return Some(entry.path.clone());
-                    }
-                } else if Path::new(&entry.path).is_dir() {
-                    return Some(entry.path.clone());
-                }
-            }
-        }
-    }
-    
-    None
+
+    let is_dir = |c: &rank::Candidate| (c.project || !projects_only) && Path::new(&c.path).is_dir();
+    rank::rank_candidates(state, &query, config)
+        .find(is_dir)
+        .or_else(|| index::search(&query, config).into_iter().find(is_dir))
+        .map(|c| c.path)
 }
 
-fn add_to_history(state: &mut AppState, path: &str) {
+/// Path equality used for history entries
+fn paths_equal(a: &str, b: &str) -> bool {
+    a.eq_ignore_ascii_case(b)
+}
+
+fn add_to_history(state: &mut AppState, path: &str, config: &Config) {
     let path = path.to_string();
     
-    if let Some(entry) = state.history.iter_mut().find(|e| e.path.eq_ignore_ascii_case(&path)) {
+    if let Some(entry) = state.history.iter_mut().find(|e| paths_equal(&e.path, &path)) {
         entry.last_visited = Utc::now();
         entry.visit_count += 1;
+        entry.missing_since = None;
     } else {
         state.history.push(HistoryEntry {
             path: path.clone(),
             last_visited: Utc::now(),
             visit_count: 1,
+            missing_since: None,
         });
     }
     
-    if state.history.len() > MAX_HISTORY {
-        state.history.sort_by(|a, b| {
-            let score_a = a.visit_count as i64 + a.last_visited.timestamp() / 86400;
-            let score_b = b.visit_count as i64 + b.last_visited.timestamp() / 86400;
-            score_b.cmp(&score_a)
-        });
-        state.history.truncate(MAX_HISTORY);
+    trim_history(&mut state.history, config);
+}
+
+/// Evict entries according to the eviction policy once history exceeds
+/// `max_history`. The remaining entries keep their order.
+fn trim_history(history: &mut Vec<HistoryEntry>, config: &Config) {
+    if history.len() <= config.max_history {
+        return;
+    }
+
+    let now = Utc::now();
+    let mut order: Vec<usize> = (0..history.len()).collect();
+    order.sort_by(|&a, &b| {
+        let (a, b) = (&history[a], &history[b]);
+        match config.eviction {
+            EvictionPolicy::Lru => b.last_visited.cmp(&a.last_visited),
+            EvictionPolicy::Lfu => b
+                .visit_count
+                .cmp(&a.visit_count)
+                .then_with(|| b.last_visited.cmp(&a.last_visited)),
+            EvictionPolicy::Frecency => {
+                let half_life = config.decay_half_life_days;
+                rank::frecency(b, now, half_life).total_cmp(&rank::frecency(a, now, half_life))
+            }
+        }
+    });
+
+    let mut keep = vec![false; history.len()];
+    for &i in order.iter().take(config.max_history) {
+        keep[i] = true;
     }
+    let mut keep = keep.into_iter();
+    history.retain(|_| keep.next().unwrap_or(false));
 }
 
-fn push_undo(state: &mut AppState, path: &str) {
-    state.undo_stack.push_back(path.to_string());
-    if state.undo_stack.len() > MAX_UNDO_STACK {
-        state.undo_stack.pop_front();
+/// Push onto an undo/redo stack, dropping the oldest entry beyond `limit`
+fn push_bounded(stack: &mut VecDeque<String>, path: &str, limit: usize) {
+    stack.push_back(path.to_string());
+    while stack.len() > limit {
+        stack.pop_front();
     }
+}
+
+fn push_undo(state: &mut AppState, path: &str, config: &Config) {
+    push_bounded(&mut state.undo_stack, path, config.max_undo);
     state.redo_stack.clear();
 }
 
+/// Pick one of the candidates for the keyword with fzf and jump there
+fn select_interactively(
+    state: &mut AppState,
+    keyword: &str,
+    projects_only: bool,
+    config: &Config,
+    current_dir: Option<&str>,
+) {
+    let candidates = search_history_all(state, keyword, projects_only, config);
+    if candidates.is_empty() {
+        eprintln!("No matches found");
+        return;
+    }
+    if let Some(selected) = run_fzf(&candidates, keyword) {
+        if Path::new(&selected).is_dir() {
+            if let Some(cur) = current_dir {
+                push_undo(state, cur, config);
+            }
+            add_to_history(state, &selected, config);
+            save_state(state).ok();
+            println!("{}", selected);
+        }
+    }
+}
+
 fn output_path(path: &Path) {
     if let Some(path_str) = path.to_str() {
         #[cfg(windows)]
@@ -528,20 +650,46 @@ fn main() {
     setup_console();
     
     let args: Vec<String> = env::args().collect();
-    let mut state = load_state();
     let config = load_config();
     
     let current_dir = env::current_dir()
         .ok()
         .and_then(|p| p.to_str().map(|s| s.to_string()));
     
+    // Run from shell hooks on every directory change, so these go to the
+    // store directly instead of loading the whole history
+    match args.get(1).map(String::as_str) {
+        Some("-c") => {
+            if let Some(ref cur) = current_dir {
+                match storage::record_visit(cur, &config) {
+                    Ok(()) => eprintln!("Recorded: {}", cur),
+                    Err(err) => eprintln!("Cannot save history: {}", err),
+                }
+            }
+            return;
+        }
+        Some("-x") => {
+            if let Some(ref cur) = current_dir {
+                match storage::forget(cur) {
+                    Ok(true) => eprintln!("Removed: {}", cur),
+                    Ok(false) => eprintln!("Not in history: {}", cur),
+                    Err(err) => eprintln!("Cannot save history: {}", err),
+                }
+            }
+            return;
+        }
+        _ => {}
+    }
+    
+    let mut state = load_state();
+    
     if args.len() < 2 {
         if let Some(home) = dirs::home_dir() {
             if let Some(ref cur) = current_dir {
-                push_undo(&mut state, cur);
+                push_undo(&mut state, cur, &config);
             }
-            add_to_history(&mut state, home.to_str().unwrap_or(""));
-            save_state(&state).ok();
+            add_to_history(&mut state, home.to_str().unwrap_or(""), &config);
+            save_state(&mut state).ok();
             output_path(&home);
         }
         return;
@@ -552,25 +700,31 @@ fn main() {
     match arg.as_str() {
         // Interactive mode with fzf
         "-i" | "--interactive" => {
-            let keyword = if args.len() > 2 {
-                args[2..].join("/")
-            } else {
-                String::new()
-            };
-            let candidates = search_history_all(&state, &keyword, &config);
-            if candidates.is_empty() {
-                eprintln!("No matches found");
-                return;
-            }
-            if let Some(selected) = run_fzf(&candidates, &keyword) {
-                if Path::new(&selected).is_dir() {
-                    if let Some(ref cur) = current_dir {
-                        push_undo(&mut state, cur);
-                    }
-                    add_to_history(&mut state, &selected);
-                    save_state(&state).ok();
-                    println!("{}", selected);
+            let projects_only = matches!(args.get(2).map(String::as_str), Some("-p" | "--project"));
+            let first = if projects_only { 3 } else { 2 };
+            let keyword = args.get(first..).map(|kw| kw.join("/")).unwrap_or_default();
+            select_interactively(&mut state, &keyword, projects_only, &config, current_dir.as_deref());
+            return;
+        }
+        // Project roots only
+        "-p" | "--project" => {
+            let interactive =
+                matches!(args.get(2).map(String::as_str), Some("-i" | "--interactive"));
+            let first = if interactive { 3 } else { 2 };
+            let keyword = args.get(first..).map(|kw| kw.join("/")).unwrap_or_default();
+            if interactive {
+                select_interactively(&mut state, &keyword, true, &config, current_dir.as_deref());
+            } else if keyword.is_empty() {
+                eprintln!("Usage: j -p <keywords>");
+            } else if let Some(path) = search_history(&state, &keyword, true, &config) {
+                if let Some(ref cur) = current_dir {
+                    push_undo(&mut state, cur, &config);
                 }
+                add_to_history(&mut state, &path, &config);
+                save_state(&mut state).ok();
+                println!("{}", path);
+            } else {
+                eprintln!("Project not found: {}", keyword);
             }
             return;
         }
@@ -581,20 +735,37 @@ fn main() {
             } else {
                 String::new()
             };
-            let candidates = search_history_all(&state, &keyword, &config);
+            let candidates = search_history_all(&state, &keyword, false, &config);
             for path in candidates {
                 println!("{}", path);
             }
             return;
         }
+        // Show how a keyword would be resolved, without jumping
+        "--explain" => {
+            if args.len() > 2 {
+                let keyword = args[2..].join("/");
+                explain::explain(&state, &keyword, &config);
+            } else {
+                eprintln!("Usage: j --explain <keywords>");
+            }
+            return;
+        }
+        // Validate data files and environment
+        "--doctor" => {
+            doctor::run();
+            return;
+        }
         // Exclude pattern management
         "--exclude-add" => {
             if let Some(pattern) = args.get(2) {
                 let mut config = load_config();
                 if !config.excludes.contains(pattern) {
                     config.excludes.push(pattern.clone());
-                    save_config(&config).ok();
-                    eprintln!("Added exclude pattern: {}", pattern);
+                    match save_config(&config) {
+                        Ok(()) => eprintln!("Added exclude pattern: {}", pattern),
+                        Err(err) => eprintln!("Cannot save config: {}", err),
+                    }
                 } else {
                     eprintln!("Pattern already exists: {}", pattern);
                 }
@@ -608,8 +779,10 @@ fn main() {
                 let mut config = load_config();
                 if let Some(pos) = config.excludes.iter().position(|p| p == pattern) {
                     config.excludes.remove(pos);
-                    save_config(&config).ok();
-                    eprintln!("Removed exclude pattern: {}", pattern);
+                    match save_config(&config) {
+                        Ok(()) => eprintln!("Removed exclude pattern: {}", pattern),
+                        Err(err) => eprintln!("Cannot save config: {}", err),
+                    }
                 } else {
                     eprintln!("Pattern not found: {}", pattern);
                 }
@@ -630,36 +803,275 @@ fn main() {
             }
             return;
         }
-        "-c" => {
-            if let Some(ref cur) = current_dir {
-                add_to_history(&mut state, cur);
-                save_state(&state).ok();
-                eprintln!("Recorded: {}", cur);
+        // Write history and aliases in another format
+        "--export" => {
+            let mut format = export::Format::Json;
+            let mut output: Option<PathBuf> = None;
+            let mut rest = args[2..].iter();
+            while let Some(opt) = rest.next() {
+                match opt.as_str() {
+                    "--format" | "-f" => match rest.next().and_then(|f| export::Format::parse(f)) {
+                        Some(f) => format = f,
+                        None => {
+                            eprintln!("Formats: json, csv, z, zoxide");
+                            return;
+                        }
+                    },
+                    "--output" | "-o" => output = rest.next().map(PathBuf::from),
+                    file => output = Some(PathBuf::from(file)),
+                }
+            }
+            let aliases = load_aliases();
+            let data = export::render(format, &state, &aliases);
+            let Some(output) = output else {
+                std::io::stdout().write_all(&data).ok();
+                return;
+            };
+            if let Err(err) = std::fs::write(&output, data) {
+                eprintln!("Cannot write {}: {}", output.display(), err);
+                return;
+            }
+            if format.has_aliases() {
+                eprintln!(
+                    "Exported {} history entries and {} aliases to {}",
+                    state.history.len(),
+                    aliases.map.len(),
+                    output.display()
+                );
+            } else {
+                eprintln!(
+                    "Exported {} history entries to {} (aliases are not part of this format)",
+                    state.history.len(),
+                    output.display()
+                );
             }
             return;
         }
-        "-x" => {
-            if let Some(ref cur) = current_dir {
-                let before_len = state.history.len();
-                state.history.retain(|e| !e.path.eq_ignore_ascii_case(cur));
-                if state.history.len() < before_len {
-                    save_state(&state).ok();
-                    eprintln!("Removed: {}", cur);
-                } else {
-                    eprintln!("Not in history: {}", cur);
+        // Import another directory jumper's database
+        "--import" => {
+            let dry_run = args.iter().any(|a| a == "--dry-run");
+            let rest: Vec<&String> = args[2..].iter().filter(|a| *a != "--dry-run").collect();
+            let format = match rest.first().and_then(|name| import::Format::parse(name)) {
+                Some(format) => format,
+                None => {
+                    eprintln!("Usage: j --import <autojump|z|fasd|zoxide> [path] [--dry-run]");
+                    return;
+                }
+            };
+            let path = match rest.get(1).map(PathBuf::from).or_else(|| format.default_path()) {
+                Some(path) => path,
+                None => {
+                    eprintln!("Cannot locate the database; pass its path");
+                    return;
+                }
+            };
+            let entries = match import::read(format, &path) {
+                Ok(entries) => entries,
+                Err(err) => {
+                    eprintln!("Cannot read {}: {}", path.display(), err);
+                    return;
+                }
+            };
+            let summary = import::merge(&mut state, entries, &config);
+            eprintln!("Read {} entries from {}", summary.read, path.display());
+            eprintln!("  {}", summary);
+            if dry_run {
+                eprintln!("Dry run: history was not changed");
+                return;
+            }
+            trim_history(&mut state.history, &config);
+            if let Err(err) = save_state(&mut state) {
+                eprintln!("Cannot save history: {}", err);
+            }
+            return;
+        }
+        // Seed history from cd commands in shell history files
+        "--import-shell-history" => {
+            let dry_run = args.iter().any(|a| a == "--dry-run");
+            let mut files: Vec<PathBuf> = args[2..]
+                .iter()
+                .filter(|a| *a != "--dry-run")
+                .map(PathBuf::from)
+                .collect();
+            if files.is_empty() {
+                files = shell_history::default_files();
+            }
+            if files.is_empty() {
+                eprintln!("No shell history found; pass the history file");
+                return;
+            }
+            let mut entries = Vec::new();
+            for file in &files {
+                match shell_history::read(file) {
+                    Ok(visits) => {
+                        eprintln!(
+                            "Read {} commands from {} ({} directory changes)",
+                            visits.commands,
+                            file.display(),
+                            visits.targets
+                        );
+                        entries.extend(visits.entries);
+                    }
+                    Err(err) => eprintln!("Cannot read {}: {}", file.display(), err),
                 }
             }
+            let summary = import::merge(&mut state, entries, &config);
+            eprintln!("  {}", summary);
+            if dry_run {
+                eprintln!("Dry run: history was not changed");
+                return;
+            }
+            trim_history(&mut state.history, &config);
+            if let Err(err) = save_state(&mut state) {
+                eprintln!("Cannot save history: {}", err);
+            }
+            return;
+        }
+        // Merge history and aliases from another machine
+        "--merge" => {
+            let dry_run = args.iter().any(|a| a == "--dry-run");
+            let path = match args[2..].iter().find(|a| *a != "--dry-run") {
+                Some(path) => PathBuf::from(path),
+                None => {
+                    eprintln!("Usage: j --merge <state.json|aliases.json|export.json> [--dry-run]");
+                    return;
+                }
+            };
+            let incoming = match merge::read(&path) {
+                Ok(incoming) => incoming,
+                Err(err) => {
+                    eprintln!("Cannot read {}: {}", path.display(), err);
+                    return;
+                }
+            };
+            eprintln!(
+                "Read {} history entries and {} aliases from {}",
+                incoming.history.len(),
+                incoming.aliases.len(),
+                path.display()
+            );
+            let has_aliases = !incoming.aliases.is_empty();
+            let history = import::merge(&mut state, incoming.history, &config);
+            let mut aliases = load_aliases();
+            let alias_summary = merge::merge_aliases(&mut aliases, incoming.aliases, &config);
+            if history.read > 0 {
+                eprintln!("  History: {}", history);
+            }
+            if has_aliases {
+                eprintln!("  Aliases: {}", alias_summary);
+            }
+            for conflict in &alias_summary.conflicts {
+                eprintln!(
+                    "  !{}: kept {}, other has {}",
+                    conflict.name, conflict.ours, conflict.theirs
+                );
+            }
+            if !alias_summary.conflicts.is_empty() {
+                eprintln!("To take the other path, remove the alias with 'j -ar <name>' and merge again");
+            }
+            if dry_run {
+                eprintln!("Dry run: history and aliases were not changed");
+                return;
+            }
+            trim_history(&mut state.history, &config);
+            if let Err(err) = save_state(&mut state) {
+                eprintln!("Cannot save history: {}", err);
+            }
+            if alias_summary.added > 0 {
+                if let Err(err) = save_aliases(&aliases) {
+                    eprintln!("Cannot save aliases: {}", err);
+                }
+            }
+            return;
+        }
+        // Directory index management
+        "--index" => {
+            let mut config = load_config();
+            match (args.get(2).map(String::as_str), args.get(3)) {
+                (Some("add"), Some(root)) => {
+                    let path = expand_home(root).unwrap_or_else(|| PathBuf::from(root));
+                    let canonical = match path.canonicalize() {
+                        Ok(canonical) if canonical.is_dir() => canonical,
+                        _ => {
+                            eprintln!("Directory not found: {}", root);
+                            return;
+                        }
+                    };
+                    let root = canonical.to_string_lossy().into_owned();
+                    if config.index_roots.contains(&root) {
+                        eprintln!("Root already indexed: {}", root);
+                        return;
+                    }
+                    config.index_roots.push(root.clone());
+                    match save_config(&config) {
+                        Ok(()) => {
+                            eprintln!("Added index root: {}", root);
+                            eprintln!("Run 'j --index update' to index it");
+                        }
+                        Err(err) => eprintln!("Cannot save config: {}", err),
+                    }
+                }
+                (Some("remove"), Some(root)) => {
+                    let canonical = expand_home(root)
+                        .unwrap_or_else(|| PathBuf::from(root))
+                        .canonicalize()
+                        .map(|path| path.to_string_lossy().into_owned());
+                    let pos = config
+                        .index_roots
+                        .iter()
+                        .position(|r| r == root || canonical.as_ref().is_ok_and(|c| r == c));
+                    let Some(pos) = pos else {
+                        eprintln!("Root not found: {}", root);
+                        return;
+                    };
+                    let removed = config.index_roots.remove(pos);
+                    if let Err(err) = save_config(&config) {
+                        eprintln!("Cannot save config: {}", err);
+                        return;
+                    }
+                    let mut index = index::Index::load();
+                    index.retain_roots(&config);
+                    match index.save() {
+                        Ok(()) => eprintln!("Removed index root: {}", removed),
+                        Err(err) => eprintln!("Cannot save index: {}", err),
+                    }
+                }
+                (Some("list"), _) => {
+                    if config.index_roots.is_empty() {
+                        eprintln!("No index roots");
+                    } else {
+                        eprintln!("Index roots:");
+                        for root in &config.index_roots {
+                            eprintln!("  {}", root);
+                        }
+                        eprintln!("{} directories indexed", index::Index::load().len());
+                    }
+                }
+                (Some("update"), _) => {
+                    if config.index_roots.is_empty() {
+                        eprintln!("No index roots; add one with 'j --index add <dir>'");
+                        return;
+                    }
+                    match index::update(&config) {
+                        Ok(update) => eprintln!(
+                            "Indexed {} directories under {} roots ({} re-read)",
+                            update.directories,
+                            config.index_roots.len(),
+                            update.reread
+                        ),
+                        Err(err) => eprintln!("Cannot save index: {}", err),
+                    }
+                }
+                _ => eprintln!("Usage: j --index add <dir> | remove <dir> | list | update"),
+            }
             return;
         }
         "-" => {
             if let Some(prev) = state.undo_stack.pop_back() {
                 if let Some(ref cur) = current_dir {
-                    state.redo_stack.push_back(cur.clone());
-                    if state.redo_stack.len() > MAX_UNDO_STACK {
-                        state.redo_stack.pop_front();
-                    }
+                    push_bounded(&mut state.redo_stack, cur, config.max_undo);
                 }
-                save_state(&state).ok();
+                save_state(&mut state).ok();
                 println!("{}", prev);
             } else {
                 eprintln!("No undo history");
@@ -669,12 +1081,9 @@ fn main() {
         "+" => {
             if let Some(next) = state.redo_stack.pop_back() {
                 if let Some(ref cur) = current_dir {
-                    state.undo_stack.push_back(cur.clone());
-                    if state.undo_stack.len() > MAX_UNDO_STACK {
-                        state.undo_stack.pop_front();
-                    }
+                    push_bounded(&mut state.undo_stack, cur, config.max_undo);
                 }
-                save_state(&state).ok();
+                save_state(&mut state).ok();
                 println!("{}", next);
             } else {
                 eprintln!("No redo history");
@@ -691,7 +1100,27 @@ fn main() {
             return;
         }
         "--list" | "-l" => {
-            let count = args.get(2).and_then(|s| s.parse::<usize>().ok()).unwrap_or(20);
+            let all = args.get(2).is_some_and(|s| s == "--all");
+            let count_arg = if all { args.get(3) } else { args.get(2) };
+            let count = count_arg.and_then(|s| s.parse::<usize>().ok()).unwrap_or(20);
+            if all {
+                // Ranked candidates, including implicit ancestors of recorded directories
+                let candidates = rank::rank_candidates(&state, &Query::default(), &config);
+                let existing = candidates.filter(|c| Path::new(&c.path).is_dir());
+                for (i, candidate) in existing.take(count).enumerate() {
+                    let implicit = if candidate.implicit { ", implicit" } else { "" };
+                    let project = if candidate.project { ", project" } else { "" };
+                    eprintln!(
+                        "{:2}. {} (score {:.2}{}{})",
+                        i + 1,
+                        candidate.path,
+                        candidate.score,
+                        implicit,
+                        project
+                    );
+                }
+                return;
+            }
             for (i, entry) in state.history.iter().rev().take(count).enumerate() {
                 eprintln!("{:2}. {} ({} visits)", i + 1, entry.path, entry.visit_count);
             }
@@ -701,8 +1130,10 @@ fn main() {
             state.history.clear();
             state.undo_stack.clear();
             state.redo_stack.clear();
-            save_state(&state).ok();
-            eprintln!("All history cleared");
+            match save_state(&mut state) {
+                Ok(()) => eprintln!("All history cleared"),
+                Err(err) => eprintln!("Cannot save history: {}", err),
+            }
             return;
         }
         "-a" => {
@@ -710,9 +1141,12 @@ fn main() {
                 if let Some(ref cur) = current_dir {
                     let mut aliases = load_aliases();
                     let is_update = aliases.map.contains_key(name);
-                    aliases.map.insert(name.clone(), cur.clone());
-                    save_aliases(&aliases).ok();
-                    if is_update {
+                    aliases
+                        .map
+                        .insert(name.clone(), rewrite::alias_path(cur, &config));
+                    if let Err(err) = save_aliases(&aliases) {
+                        eprintln!("Cannot save aliases: {}", err);
+                    } else if is_update {
                         eprintln!("Updated: {} -> {}", name, cur);
                     } else {
                         eprintln!("{} -> {}", name, cur);
@@ -729,8 +1163,10 @@ fn main() {
             if let Some(name) = args.get(2) {
                 let mut aliases = load_aliases();
                 if aliases.map.remove(name).is_some() {
-                    save_aliases(&aliases).ok();
-                    eprintln!("Alias removed: {}", name);
+                    match save_aliases(&aliases) {
+                        Ok(()) => eprintln!("Alias removed: {}", name),
+                        Err(err) => eprintln!("Cannot save aliases: {}", err),
+                    }
                 } else {
                     eprintln!("Alias not found: {}", name);
                 }
@@ -762,8 +1198,11 @@ fn main() {
             eprintln!("  j <keyword>        Jump to directory matching keyword");
             eprintln!("  j <kw1> <kw2> ...  Jump using multiple keywords (in order)");
             eprintln!("  j -i [keyword]     Interactive selection with fzf");
+            eprintln!("  j -p <keyword>     Jump to a project root (.git, Cargo.toml, ...)");
+            eprintln!("  j -p -i [keyword]  Interactive selection of project roots");
             eprintln!("  j !<alias>         Jump to aliased directory");
             eprintln!("  j ~<path>          Jump to path under home directory");
+            eprintln!("  j --explain <kw>   Show how keywords are resolved (no jump)");
             eprintln!();
             eprintln!("History:");
             eprintln!("  j -                Go back (Undo)");
@@ -773,6 +1212,7 @@ fn main() {
             eprintln!("  j -x               Remove current directory from history");
             eprintln!("  j -xa              Clear all history");
             eprintln!("  j -l [N]           List history (default 20)");
+            eprintln!("  j -l --all [N]     List ranked directories, including implicit parents");
             eprintln!("  j -N               Jump to Nth entry (e.g., j -1, j -5)");
             eprintln!();
             eprintln!("Aliases:");
@@ -785,6 +1225,22 @@ fn main() {
             eprintln!("  j --exclude-remove <pattern> Remove exclude pattern");
             eprintln!("  j --exclude-list             List exclude patterns");
             eprintln!();
+            eprintln!("Index:");
+            eprintln!("  j --index add <dir>          Index every directory below <dir>");
+            eprintln!("  j --index remove <dir>       Stop indexing <dir>");
+            eprintln!("  j --index list               List index roots");
+            eprintln!("  j --index update             Re-scan index roots (only changed directories)");
+            eprintln!();
+            eprintln!("Import and export:");
+            eprintln!("  j --import <format> [path]   Import autojump, z, fasd or zoxide data");
+            eprintln!("  j --import <format> --dry-run  Show what would be imported");
+            eprintln!("  j --export --format <fmt> [file]  Export as json, csv, z or zoxide");
+            eprintln!("  j --import-shell-history [file]  Seed history from cd commands in shell history");
+            eprintln!("  j --merge <file>   Merge history and aliases from another machine");
+            eprintln!();
+            eprintln!("Maintenance:");
+            eprintln!("  j --doctor         Check data files, dead entries and shell setup");
+            eprintln!();
             eprintln!("Examples:");
             eprintln!("  j src              Jump to 'src' directory");
             eprintln!("  j proj src         Jump with keywords in order");
@@ -799,8 +1255,8 @@ fn main() {
         _ => {}
     }
     
-    if arg.starts_with('-') {
-        if let Ok(num) = arg[1..].parse::<usize>() {
+    if let Some(num_str) = arg.strip_prefix('-') {
+        if let Ok(num) = num_str.parse::<usize>() {
             if num > 0 {
                 let index = num - 1;
                 let history_len = state.history.len();
@@ -808,10 +1264,10 @@ fn main() {
                     let target_path = state.history[history_len - 1 - index].path.clone();
                     if Path::new(&target_path).is_dir() {
                         if let Some(ref cur) = current_dir {
-                            push_undo(&mut state, cur);
+                            push_undo(&mut state, cur, &config);
                         }
-                        add_to_history(&mut state, &target_path);
-                        save_state(&state).ok();
+                        add_to_history(&mut state, &target_path, &config);
+                        save_state(&mut state).ok();
                         println!("{}", target_path);
                     } else {
                         eprintln!("Directory not found: {}", target_path);
@@ -824,17 +1280,17 @@ fn main() {
         }
     }
     
-    if arg.starts_with('!') {
-        let alias_name = &arg[1..];
+    if let Some(alias_name) = arg.strip_prefix('!') {
         let aliases = load_aliases();
         if let Some(path_str) = aliases.map.get(alias_name) {
+            let path_str = &rewrite::localize(path_str, &config);
             let path = PathBuf::from(path_str);
             if path.is_dir() {
                 if let Some(ref cur) = current_dir {
-                    push_undo(&mut state, cur);
+                    push_undo(&mut state, cur, &config);
                 }
-                add_to_history(&mut state, path_str);
-                save_state(&state).ok();
+                add_to_history(&mut state, path_str, &config);
+                save_state(&mut state).ok();
                 output_path(&path);
             } else {
                 eprintln!("Directory does not exist: {}", path_str);
@@ -849,10 +1305,10 @@ fn main() {
         if let Some(path) = expand_home(arg) {
             if path.is_dir() {
                 if let Some(ref cur) = current_dir {
-                    push_undo(&mut state, cur);
+                    push_undo(&mut state, cur, &config);
                 }
-                add_to_history(&mut state, path.to_str().unwrap_or(""));
-                save_state(&state).ok();
+                add_to_history(&mut state, path.to_str().unwrap_or(""), &config);
+                save_state(&mut state).ok();
                 output_path(&path);
             } else {
                 eprintln!("Directory not found: {}", path.display());
@@ -884,10 +1340,10 @@ fn main() {
         
         if path.is_dir() {
             if let Some(ref cur) = current_dir {
-                push_undo(&mut state, cur);
+                push_undo(&mut state, cur, &config);
             }
-            add_to_history(&mut state, path.to_str().unwrap_or(""));
-            save_state(&state).ok();
+            add_to_history(&mut state, path.to_str().unwrap_or(""), &config);
+            save_state(&mut state).ok();
             output_path(&path);
         } else {
             eprintln!("Directory not found: {}", path.display());
@@ -912,10 +1368,10 @@ fn main() {
                     let clean_path = path_str;
                     
                     if let Some(ref cur) = current_dir {
-                        push_undo(&mut state, cur);
+                        push_undo(&mut state, cur, &config);
                     }
-                    add_to_history(&mut state, clean_path);
-                    save_state(&state).ok();
+                    add_to_history(&mut state, clean_path, &config);
+                    save_state(&mut state).ok();
                     output_path(&canonical);
                     return;
                 }
@@ -932,10 +1388,10 @@ fn main() {
             let path = PathBuf::from(&drive_root);
             if path.is_dir() {
                 if let Some(ref cur) = current_dir {
-                    push_undo(&mut state, cur);
+                    push_undo(&mut state, cur, &config);
                 }
-                add_to_history(&mut state, &drive_root);
-                save_state(&state).ok();
+                add_to_history(&mut state, &drive_root, &config);
+                save_state(&mut state).ok();
                 output_path(&path);
             }
             return;
@@ -966,10 +1422,10 @@ fn main() {
         
         if let Some(found) = found_path {
             if let Some(ref cur) = current_dir {
-                push_undo(&mut state, cur);
+                push_undo(&mut state, cur, &config);
             }
-            add_to_history(&mut state, &found);
-            save_state(&state).ok();
+            add_to_history(&mut state, &found, &config);
+            save_state(&mut state).ok();
             println!("{}", found);
             return;
         }
@@ -987,23 +1443,53 @@ fn main() {
     
     if let Some(path) = try_local_path(&search_keyword) {
         if let Some(ref cur) = current_dir {
-            push_undo(&mut state, cur);
+            push_undo(&mut state, cur, &config);
         }
-        add_to_history(&mut state, path.to_str().unwrap_or(""));
-        save_state(&state).ok();
+        add_to_history(&mut state, path.to_str().unwrap_or(""), &config);
+        save_state(&mut state).ok();
         output_path(&path);
         return;
     }
     
-    if let Some(path) = search_history(&state, &search_keyword, &config) {
+    if let Some(path) = search_history(&state, &search_keyword, false, &config) {
         if let Some(ref cur) = current_dir {
-            push_undo(&mut state, cur);
+            push_undo(&mut state, cur, &config);
         }
-        add_to_history(&mut state, &path);
-        save_state(&state).ok();
+        add_to_history(&mut state, &path, &config);
+        save_state(&mut state).ok();
         println!("{}", path);
         return;
     }
-    
+
+    let query = Query::parse(&search_keyword, &config);
+    if let Some(cur) = &current_dir {
+        if let Some(path) = walk::search(Path::new(cur), &query, &config).found {
+            let path = path.to_string_lossy().into_owned();
+            push_undo(&mut state, cur, &config);
+            add_to_history(&mut state, &path, &config);
+            save_state(&mut state).ok();
+            println!("{}", path);
+            return;
+        }
+    }
+
+    let suggestions = typo::suggest(&state, &query, &config);
+    if let Some(winner) = typo::clear_winner(&suggestions) {
+        let path = winner.path.clone();
+        if let Some(ref cur) = current_dir {
+            push_undo(&mut state, cur, &config);
+        }
+        add_to_history(&mut state, &path, &config);
+        save_state(&mut state).ok();
+        println!("{}", path);
+        return;
+    }
+
     eprintln!("Directory not found: {}", search_keyword);
+    if !suggestions.is_empty() {
+        eprintln!("Did you mean:");
+        for suggestion in suggestions.iter().take(typo::MAX_SUGGESTIONS) {
+            eprintln!("  {}", suggestion.path);
+        }
+    }
 }
@@ -0,0 +1,140 @@
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::export::EXPORT_VERSION;
+use crate::import::Imported;
+use crate::rewrite;
+use crate::schema::{self, Versioned};
+use crate::{paths_equal, Aliases, AppState, Config, HistoryEntry};
+
+/// History and aliases read from another machine
+pub struct Incoming {
+    pub history: Vec<Imported>,
+    pub aliases: HashMap<String, String>,
+}
+
+/// An alias defined with a different path on each side
+pub struct Conflict {
+    pub name: String,
+    pub ours: String,
+    pub theirs: String,
+}
+
+/// Outcome of merging aliases
+#[derive(Default)]
+pub struct AliasSummary {
+    pub added: usize,
+    /// Same name and path on both sides
+    pub identical: usize,
+    pub conflicts: Vec<Conflict>,
+}
+
+impl fmt::Display for AliasSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} new, {} already defined, {} conflicting",
+            self.added,
+            self.identical,
+            self.conflicts.len()
+        )
+    }
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Parse a document of a versioned file kind, migrating it from older schemas
+fn parse_versioned<T: Versioned>(mut value: Value, kind: &str, limit: u32) -> io::Result<T> {
+    let version = schema::version_of(&value);
+    if version > limit {
+        return Err(invalid(format!(
+            "{} uses schema v{}, newer than this j (v{})",
+            kind, version, limit
+        )));
+    }
+    schema::migrate::<T>(&mut value);
+    serde_json::from_value(value).map_err(|err| invalid(format!("invalid {}: {}", kind, err)))
+}
+
+/// Read a state.json, an aliases.json or a `j --export` JSON file, from any
+/// schema version. Only exports hold both history and aliases; undo stacks
+/// and other per-machine state are ignored.
+pub fn read(path: &Path) -> io::Result<Incoming> {
+    let value: Value = serde_json::from_str(&fs::read_to_string(path)?)
+        .map_err(|err| invalid(format!("invalid JSON: {}", err)))?;
+    if !value.is_object() {
+        return Err(invalid("neither history nor aliases found".to_string()));
+    }
+    let has_history = value.get("history").is_some_and(Value::is_array);
+    let has_aliases = value.get("aliases").is_some_and(Value::is_object);
+
+    let (history, aliases) = if has_history && has_aliases {
+        let version = schema::version_of(&value);
+        if version > EXPORT_VERSION {
+            return Err(invalid(format!(
+                "export uses layout v{}, newer than this j (v{})",
+                version, EXPORT_VERSION
+            )));
+        }
+        let history: Vec<HistoryEntry> = serde_json::from_value(value["history"].clone())
+            .map_err(|err| invalid(format!("invalid history: {}", err)))?;
+        let aliases: HashMap<String, String> = serde_json::from_value(value["aliases"].clone())
+            .map_err(|err| invalid(format!("invalid aliases: {}", err)))?;
+        (history, aliases)
+    } else if has_history {
+        let state: AppState = parse_versioned(value, "state", AppState::VERSION)?;
+        (state.history, HashMap::new())
+    } else {
+        // aliases.json, including the flat map written before versioning,
+        // where an alias may itself be called `history` or `aliases`
+        let aliases: Aliases = parse_versioned(value, "aliases", Aliases::VERSION)?;
+        if aliases.map.is_empty() && !has_aliases {
+            return Err(invalid("neither history nor aliases found".to_string()));
+        }
+        (Vec::new(), aliases.map)
+    };
+    Ok(Incoming {
+        history: history.into_iter().map(Into::into).collect(),
+        aliases,
+    })
+}
+
+/// Add aliases that are not defined here. Paths are compared after mapping
+/// both sides to this machine with `path_rewrites`; an alias defined here
+/// with another path keeps the local path and is reported as a conflict.
+pub fn merge_aliases(
+    aliases: &mut Aliases,
+    incoming: HashMap<String, String>,
+    config: &Config,
+) -> AliasSummary {
+    let mut summary = AliasSummary::default();
+    // Sorted so conflicts are reported in a stable order
+    let mut incoming: Vec<(String, String)> = incoming.into_iter().collect();
+    incoming.sort();
+    for (name, theirs) in incoming {
+        let theirs = rewrite::localize(&theirs, config);
+        match aliases.map.get(&name) {
+            Some(ours) if paths_equal(&rewrite::localize(ours, config), &theirs) => {
+                summary.identical += 1
+            }
+            Some(ours) => summary.conflicts.push(Conflict {
+                name,
+                ours: ours.clone(),
+                theirs,
+            }),
+            None => {
+                aliases
+                    .map
+                    .insert(name, rewrite::alias_path(&theirs, config));
+                summary.added += 1;
+            }
+        }
+    }
+    summary
+}
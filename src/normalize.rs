@@ -0,0 +1,34 @@
+use unicode_normalization::UnicodeNormalization;
+
+use crate::{split_path, Config};
+
+/// Combining marks folded away by `fold_diacritics`. Only the Latin block is
+/// stripped so that kana voicing marks (゛ ゜) keep が distinct from か.
+fn is_latin_diacritic(c: char) -> bool {
+    ('\u{0300}'..='\u{036f}').contains(&c)
+}
+
+/// Normalized components of a stored path. The path is split first, since
+/// NFKC turns a full-width slash inside a directory name into `/`.
+pub fn normalize_parts(path: &str, config: &Config) -> Vec<String> {
+    split_path(path)
+        .into_iter()
+        .map(|part| normalize(part, config))
+        .collect()
+}
+
+/// Canonical form used whenever a query is compared with a stored path.
+/// NFKC makes NFD names (as stored by macOS) equal to NFC input and folds
+/// full-width letters to ASCII and half-width katakana to full width;
+/// `fold_diacritics` additionally drops accents so that `é` matches `e`.
+/// Case is left alone; only the compared copy is changed, never the path.
+pub fn normalize(s: &str, config: &Config) -> String {
+    // ASCII is already in every normal form; this keeps large index scans fast
+    if s.is_ascii() {
+        s.to_string()
+    } else if config.fold_diacritics {
+        s.nfkd().filter(|c| !is_latin_diacritic(*c)).nfc().collect()
+    } else {
+        s.nfkc().collect()
+    }
+}
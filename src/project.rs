@@ -0,0 +1,27 @@
+use std::path::Path;
+
+use crate::Config;
+
+/// Whether a directory holds one of the `project_markers`, such as `.git`
+/// or `Cargo.toml`
+pub fn is_root(path: &str, config: &Config) -> bool {
+    let dir = Path::new(path);
+    config
+        .project_markers
+        .iter()
+        .any(|marker| dir.join(marker).exists())
+}
+
+/// Whether a name read from a directory listing is a project marker
+pub fn is_marker(name: &str, config: &Config) -> bool {
+    config.project_markers.iter().any(|marker| marker == name)
+}
+
+/// Score multiplier: `1 + project_weight` for project roots, 1 otherwise
+pub fn factor(is_root: bool, config: &Config) -> f64 {
+    if is_root {
+        1.0 + config.project_weight
+    } else {
+        1.0
+    }
+}
@@ -0,0 +1,59 @@
+use std::env;
+use std::path::{Component, Path, PathBuf};
+
+/// Where j was run from, used to favour candidates close to it
+pub struct Proximity {
+    cwd: Option<PathBuf>,
+    /// Root of the git repository containing the current directory
+    git_root: Option<PathBuf>,
+}
+
+/// Path components compared for proximity, ignoring ASCII case like history paths
+fn components(path: &Path) -> Vec<String> {
+    path.components()
+        .filter(|c| !matches!(c, Component::CurDir))
+        .map(|c| c.as_os_str().to_string_lossy().to_ascii_lowercase())
+        .collect()
+}
+
+/// Number of leading components two paths have in common
+fn shared_prefix(a: &[String], b: &[String]) -> usize {
+    a.iter().zip(b).take_while(|(x, y)| x == y).count()
+}
+
+impl Proximity {
+    pub fn current() -> Self {
+        let cwd = env::current_dir().ok();
+        let git_root = cwd
+            .as_deref()
+            .and_then(|cwd| cwd.ancestors().find(|dir| dir.join(".git").exists()))
+            .map(Path::to_path_buf);
+        Proximity { cwd, git_root }
+    }
+
+    pub fn cwd(&self) -> Option<&Path> {
+        self.cwd.as_deref()
+    }
+
+    pub fn git_root(&self) -> Option<&Path> {
+        self.git_root.as_deref()
+    }
+
+    /// Score multiplier in [1, 1 + weight]. Half of the weight is earned by
+    /// the share of the current directory's components the path starts with,
+    /// the other half by lying inside the current git repository.
+    pub fn factor(&self, path: &str, weight: f64) -> f64 {
+        let cwd = match &self.cwd {
+            Some(cwd) if weight > 0.0 => components(cwd),
+            _ => return 1.0,
+        };
+        let path = components(Path::new(path));
+        let shared = shared_prefix(&path, &cwd) as f64 / cwd.len().max(1) as f64;
+        let in_repo = self.git_root.as_deref().is_some_and(|root| {
+            let root = components(root);
+            shared_prefix(&path, &root) == root.len()
+        });
+        let repo = if in_repo { 1.0 } else { 0.0 };
+        1.0 + weight * (shared + repo) / 2.0
+    }
+}
@@ -0,0 +1,161 @@
+use std::borrow::Cow;
+use std::fmt;
+
+use crate::normalize::normalize;
+use crate::{split_query, Config};
+
+/// One search term of a query
+#[derive(Debug, Clone)]
+pub struct Term {
+    /// Normalized text, lowercased unless `case_sensitive`
+    pub text: String,
+    /// `^term`: must match at the start of a path component
+    pub anchored_start: bool,
+    /// `term$`: the final path component must end with the term
+    pub anchored_end: bool,
+    /// `=term`: must equal a whole path component
+    pub exact: bool,
+    /// Typed with an ASCII uppercase letter, so case matters (smart case)
+    pub case_sensitive: bool,
+}
+
+impl Term {
+    /// Parse a normalized term. Case sensitivity is decided by the caller
+    /// from the term as typed, before normalization.
+    fn parse(raw: &str, case_sensitive: bool) -> Option<Self> {
+        let (exact, raw) = match raw.strip_prefix('=') {
+            Some(rest) => (true, rest),
+            None => (false, raw),
+        };
+        let (anchored_start, raw) = match raw.strip_prefix('^') {
+            Some(rest) => (true, rest),
+            None => (false, raw),
+        };
+        let (anchored_end, raw) = match raw.strip_suffix('$') {
+            Some(rest) => (true, rest),
+            None => (false, raw),
+        };
+        if raw.is_empty() {
+            return None;
+        }
+        Some(Term {
+            text: if case_sensitive {
+                raw.to_string()
+            } else {
+                raw.to_lowercase()
+            },
+            anchored_start,
+            anchored_end,
+            exact,
+            case_sensitive,
+        })
+    }
+
+    /// A term without operators or uppercase, which may also match loosely
+    /// (kana, fuzzy and typo matching)
+    pub fn is_plain(&self) -> bool {
+        !(self.anchored_start || self.anchored_end || self.exact || self.case_sensitive)
+    }
+
+    /// Text compared with this term: lowercased unless the term is case-sensitive
+    fn fold<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        if self.case_sensitive {
+            Cow::Borrowed(text)
+        } else {
+            Cow::Owned(text.to_lowercase())
+        }
+    }
+
+    /// Whether a normalized path component is exactly this term
+    pub fn equals(&self, part: &str) -> bool {
+        self.fold(part) == self.text
+    }
+
+    /// Whether a normalized path component satisfies this term literally
+    pub fn matches(&self, part: &str) -> bool {
+        let part = self.fold(part);
+        if self.exact {
+            part == self.text
+        } else {
+            part.contains(&self.text)
+                && (!self.anchored_start || part.starts_with(&self.text))
+                && (!self.anchored_end || part.ends_with(&self.text))
+        }
+    }
+}
+
+impl fmt::Display for Term {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let exact = if self.exact { "=" } else { "" };
+        let start = if self.anchored_start { "^" } else { "" };
+        let end = if self.anchored_end { "$" } else { "" };
+        write!(f, "{}{}{}{}", exact, start, self.text, end)
+    }
+}
+
+/// A parsed keyword. Separators split it into terms, and each term may carry
+/// operators: `^work` anchors to the start of a component, `src$` requires
+/// the final component to end with `src`, `=src` requires a whole component,
+/// and `%test` rejects paths containing `test`. An ASCII uppercase letter
+/// makes a term case-sensitive; full-width letters do not, even though NFKC
+/// turns `Ｐ` into `P`.
+#[derive(Debug, Clone, Default)]
+pub struct Query {
+    /// Positive terms in order; the last one matches the final directory name
+    pub terms: Vec<Term>,
+    /// `%term`: paths containing any of these are rejected
+    pub negations: Vec<Term>,
+}
+
+impl Query {
+    pub fn parse(keyword: &str, config: &Config) -> Self {
+        let mut query = Query::default();
+        for typed in split_query(keyword) {
+            let case_sensitive = typed.chars().any(|c| c.is_ascii_uppercase());
+            let raw = normalize(typed, config);
+            match raw.strip_prefix('%') {
+                Some(negated) => query
+                    .negations
+                    .extend(Term::parse(negated, case_sensitive)),
+                None => query.terms.extend(Term::parse(&raw, case_sensitive)),
+            }
+        }
+        query
+    }
+
+    /// No positive terms; every path not rejected by a negation matches
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    /// Whether a normalized path contains any negated term
+    pub fn rejects(&self, path: &str) -> bool {
+        self.negations
+            .iter()
+            .any(|term| term.fold(path).contains(&term.text))
+    }
+
+    /// Terms other than the last that must also match the final component
+    pub fn final_terms(&self) -> impl Iterator<Item = &Term> {
+        let n = self.terms.len().saturating_sub(1);
+        self.terms[..n].iter().filter(|term| term.anchored_end)
+    }
+
+    /// Terms matched in order against the parent directories
+    pub fn parent_terms(&self) -> Vec<&Term> {
+        let n = self.terms.len().saturating_sub(1);
+        self.terms[..n]
+            .iter()
+            .filter(|term| !term.anchored_end)
+            .collect()
+    }
+}
+
+impl fmt::Display for Query {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let terms = self.terms.iter().map(Term::to_string);
+        let negations = self.negations.iter().map(|term| format!("%{}", term));
+        let all: Vec<String> = terms.chain(negations).collect();
+        write!(f, "{}", all.join(", "))
+    }
+}
@@ -0,0 +1,438 @@
+use chrono::{DateTime, Utc};
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::Path;
+
+use crate::fuzzy;
+use crate::kana::{self, KanaForms};
+use crate::normalize::{normalize, normalize_parts};
+use crate::project;
+use crate::proximity::Proximity;
+use crate::query::{Query, Term};
+use crate::{is_excluded, tokens_match_in_order, AppState, Config, HistoryEntry};
+
+/// How well a history entry matched the query
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MatchTier {
+    /// Each keyword is a scattered subsequence of a directory name, e.g. `mfp`
+    /// for `my-fancy-project`
+    Fuzzy,
+    /// Last keyword matches a parent of the recorded directory, which is
+    /// where the match resolves to
+    Ancestor,
+    /// Last keyword, typed in romaji or either kana, is contained in the
+    /// romaji or hiragana form of a kana directory name
+    Kana,
+    /// Last keyword is contained in the final directory name
+    Partial,
+    /// Last keyword equals the final directory name
+    Exact,
+}
+
+impl MatchTier {
+    pub fn weight(self) -> f64 {
+        match self {
+            MatchTier::Exact => 4.0,
+            MatchTier::Partial => 1.0,
+            MatchTier::Kana => 1.0,
+            MatchTier::Ancestor => 0.5,
+            MatchTier::Fuzzy => 0.5,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            MatchTier::Exact => "exact",
+            MatchTier::Partial => "partial",
+            MatchTier::Kana => "kana",
+            MatchTier::Ancestor => "ancestor",
+            MatchTier::Fuzzy => "fuzzy",
+        }
+    }
+}
+
+/// The tier a path matched at, and how closely
+#[derive(Debug, Clone)]
+pub struct Match {
+    pub tier: MatchTier,
+    /// In (0, 1]; below 1 only for fuzzy and partial ancestor matches
+    pub quality: f64,
+    /// Ancestor directory to jump to instead of the matched path
+    pub resolved: Option<String>,
+}
+
+impl Match {
+    fn full(tier: MatchTier) -> Self {
+        Match {
+            tier,
+            quality: 1.0,
+            resolved: None,
+        }
+    }
+}
+
+/// A history entry that matched the query, with its score and the factors
+/// it was computed from
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    pub path: String,
+    pub last_visited: DateTime<Utc>,
+    pub score: f64,
+    /// Derived from recorded descendants rather than visited itself
+    pub implicit: bool,
+    /// Contains a project marker; included in `score`
+    pub project: bool,
+    pub tier: MatchTier,
+    /// Frecency before match weighting, summed over descendants when implicit
+    pub frecency: f64,
+    /// Multiplier for closeness to the current directory; included in `score`
+    pub proximity: f64,
+    /// Visit count of a recorded entry
+    pub visits: Option<u32>,
+    /// The matched path, when the candidate is an ancestor it resolved to
+    pub via: Option<String>,
+    /// The matched path is excluded; only `breakdown` keeps such candidates
+    pub excluded: bool,
+}
+
+/// Share of a directory's frecency credited to its parent; halves again per level
+const ANCESTOR_DECAY: f64 = 0.5;
+
+/// A directory never recorded itself, ranked by the recorded directories below it
+#[derive(Debug, Clone)]
+pub struct ImplicitAncestor {
+    pub path: String,
+    /// Most recent visit to any descendant
+    pub last_visited: DateTime<Utc>,
+    /// Sum of the descendants' frecency, discounted by `ANCESTOR_DECAY` per level
+    pub frecency: f64,
+}
+
+/// Visit count decayed exponentially with the age of the last visit, halving
+/// every `half_life_days`. Used both for ranking and for frecency eviction.
+pub fn frecency(entry: &HistoryEntry, now: DateTime<Utc>, half_life_days: f64) -> f64 {
+    let age_days = (now - entry.last_visited).num_seconds().max(0) as f64 / 86400.0;
+    entry.visit_count as f64 * 0.5_f64.powf(age_days / half_life_days)
+}
+
+/// Frecency weighted by how well the path matched
+pub fn weighted(frecency: f64, m: &Match) -> f64 {
+    frecency * m.tier.weight() * m.quality
+}
+
+/// Derive the unrecorded ancestors of non-excluded history entries.
+/// The filesystem root, the home directory and anything above it are left out,
+/// as are ancestors that are recorded or excluded themselves.
+/// These exist only for ranking and are never added to the history.
+pub fn implicit_ancestors(history: &[HistoryEntry], config: &Config) -> Vec<ImplicitAncestor> {
+    let now = Utc::now();
+    let home = dirs::home_dir();
+    let recorded: HashSet<String> = history
+        .iter()
+        .map(|entry| entry.path.to_ascii_lowercase())
+        .collect();
+
+    let mut ancestors: Vec<ImplicitAncestor> = Vec::new();
+    let mut index: HashMap<String, usize> = HashMap::new();
+    for entry in history.iter().filter(|e| !is_excluded(&e.path, config)) {
+        let base = frecency(entry, now, config.decay_half_life_days);
+        let mut share = base;
+        for dir in Path::new(&entry.path).ancestors().skip(1) {
+            share *= ANCESTOR_DECAY;
+            if dir.parent().is_none() || home.as_deref() == Some(dir) {
+                break;
+            }
+            let path = dir.to_string_lossy().into_owned();
+            let key = path.to_ascii_lowercase();
+            if recorded.contains(&key) || is_excluded(&path, config) {
+                continue;
+            }
+            match index.get(&key) {
+                Some(&i) => {
+                    let ancestor = &mut ancestors[i];
+                    ancestor.frecency += share;
+                    ancestor.last_visited = ancestor.last_visited.max(entry.last_visited);
+                }
+                None => {
+                    index.insert(key, ancestors.len());
+                    ancestors.push(ImplicitAncestor {
+                        path,
+                        last_visited: entry.last_visited,
+                        frecency: share,
+                    });
+                }
+            }
+        }
+    }
+    ancestors
+}
+
+/// Whether a term matches a path component literally or, for plain terms,
+/// through the romaji or hiragana form of a kana component
+fn kana_matches(term: &Term, part: &str) -> bool {
+    term.matches(part)
+        || (term.is_plain()
+            && kana::has_kana(part)
+            && KanaForms::of_name(&part.to_lowercase()).contains(&KanaForms::of_token(&term.text)))
+}
+
+/// Whether a term matches a path component literally or, for plain terms,
+/// as a fuzzy subsequence
+pub fn fuzzy_matches(term: &Term, part: &str) -> bool {
+    term.matches(part) || (term.is_plain() && fuzzy::score(&term.text, part).is_some())
+}
+
+/// Match the last term against a parent directory of the path, nearest first,
+/// preferring a whole name over a partial one. Terms marked `$` must match
+/// the final component, so they rule this out.
+fn match_ancestor(path: &str, parts: &[&str], query: &Query) -> Option<Match> {
+    let last = query.terms.last()?;
+    if last.anchored_end || query.final_terms().next().is_some() {
+        return None;
+    }
+    let ancestors = &parts[..parts.len().saturating_sub(1)];
+    let parent_terms = query.parent_terms();
+    let fits = |i: usize, matches: &dyn Fn(&str) -> bool| {
+        matches(ancestors[i])
+            && tokens_match_in_order(&ancestors[..i], &parent_terms, |term, part| {
+                term.matches(part)
+            })
+    };
+    let (index, quality) = (0..ancestors.len())
+        .rev()
+        .find(|&i| fits(i, &|part| last.equals(part)))
+        .map(|i| (i, 1.0))
+        .or_else(|| {
+            (0..ancestors.len())
+                .rev()
+                .find(|&i| fits(i, &|part| last.matches(part)))
+                .map(|i| (i, 0.5))
+        })?;
+    let resolved = Path::new(path).ancestors().nth(parts.len() - 1 - index)?;
+    Some(Match {
+        tier: MatchTier::Ancestor,
+        quality,
+        resolved: Some(resolved.to_string_lossy().into_owned()),
+    })
+}
+
+/// Determine how a path matches the query.
+/// The last term must match the final directory name; the other terms
+/// must appear in order among the parent directories. When there is no
+/// exact or partial match, kana names are compared in romaji and hiragana
+/// (if `kana_matching` is on), then the last term may match a parent directory,
+/// then plain terms are tried as fuzzy subsequences.
+/// The path is normalized the same way as the query before comparing.
+pub fn match_path(path: &str, query: &Query, config: &Config) -> Option<Match> {
+    let last = query.terms.last()?;
+    let normalized = normalize(path, config);
+    if query.rejects(&normalized) {
+        return None;
+    }
+    let owned_parts = normalize_parts(path, config);
+    let path_parts: Vec<&str> = owned_parts.iter().map(String::as_str).collect();
+    let (dir_name, parents) = path_parts.split_last()?;
+    if !query.final_terms().all(|term| term.matches(dir_name)) {
+        return None;
+    }
+    let parent_terms = query.parent_terms();
+
+    let tier = if last.equals(dir_name) {
+        Some(MatchTier::Exact)
+    } else if last.matches(dir_name) {
+        Some(MatchTier::Partial)
+    } else {
+        None
+    };
+    if let Some(tier) = tier {
+        if tokens_match_in_order(parents, &parent_terms, |term, part| term.matches(part)) {
+            return Some(Match::full(tier));
+        }
+    }
+
+    if config.kana_matching
+        && last.is_plain()
+        && kana_matches(last, dir_name)
+        && tokens_match_in_order(parents, &parent_terms, |term, part| kana_matches(term, part))
+    {
+        return Some(Match::full(MatchTier::Kana));
+    }
+
+    if let Some(m) = match_ancestor(path, &path_parts, query) {
+        return Some(m);
+    }
+    if !last.is_plain() {
+        return None;
+    }
+
+    let quality = fuzzy::quality(&last.text, dir_name)?;
+    if !tokens_match_in_order(parents, &parent_terms, |term, part| fuzzy_matches(term, part)) {
+        return None;
+    }
+    Some(Match {
+        tier: MatchTier::Fuzzy,
+        quality,
+        resolved: None,
+    })
+}
+
+/// Like `match_path`, but a query without positive terms matches every
+/// path it does not reject
+pub fn match_query(path: &str, query: &Query, config: &Config) -> Option<Match> {
+    if !query.is_empty() {
+        return match_path(path, query, config);
+    }
+    if query.rejects(&normalize(path, config)) {
+        return None;
+    }
+    Some(Match::full(MatchTier::Partial))
+}
+
+/// Score every non-excluded history entry and implicit ancestor matching
+/// the query, best first. Scores are boosted by proximity to the current
+/// directory and for project roots, which are detected only as far as the
+/// result is consumed. Paths reached more than once are listed once, at
+/// their best score. Existence on disk is not checked here so callers can
+/// stop at the first hit.
+pub fn rank_candidates<'a>(state: &AppState, query: &Query, config: &'a Config) -> Ranked<'a> {
+    let mut candidates = scored(state, query, config);
+    candidates.retain(|c| !c.excluded);
+    Ranked::new(candidates, config)
+}
+
+/// Like `rank_candidates`, but excluded entries are kept in place and marked,
+/// so `--explain` can show why they were passed over
+pub fn breakdown<'a>(state: &AppState, query: &Query, config: &'a Config) -> Ranked<'a> {
+    Ranked::new(scored(state, query, config), config)
+}
+
+/// Every history entry and implicit ancestor matching the query, sorted
+/// without the project boost. Excluded entries do not hide a later one
+/// reaching the same path.
+fn scored(state: &AppState, query: &Query, config: &Config) -> Vec<Candidate> {
+    let now = Utc::now();
+    let proximity = Proximity::current();
+    let near = |path: &str| proximity.factor(path, config.proximity_weight);
+    let history = state.ranked_history();
+    let recorded = history.iter().filter_map(|entry| {
+        let m = match_query(&entry.path, query, config)?;
+        let path = m.resolved.clone().unwrap_or_else(|| entry.path.clone());
+        let frecency = frecency(entry, now, config.decay_half_life_days);
+        let proximity = near(&path);
+        Some(Candidate {
+            score: weighted(frecency, &m) * proximity,
+            last_visited: entry.last_visited,
+            implicit: false,
+            project: false,
+            tier: m.tier,
+            frecency,
+            proximity,
+            visits: Some(entry.visit_count),
+            via: m.resolved.as_ref().map(|_| entry.path.clone()),
+            excluded: is_excluded(&entry.path, config),
+            path,
+        })
+    });
+    let implicit = implicit_ancestors(&history, config)
+        .into_iter()
+        .filter_map(|ancestor| {
+            let m = match_query(&ancestor.path, query, config)?;
+            let path = m.resolved.clone().unwrap_or_else(|| ancestor.path.clone());
+            let proximity = near(&path);
+            Some(Candidate {
+                score: weighted(ancestor.frecency, &m) * proximity,
+                last_visited: ancestor.last_visited,
+                implicit: true,
+                project: false,
+                tier: m.tier,
+                frecency: ancestor.frecency,
+                proximity,
+                visits: None,
+                via: m.resolved.map(|_| ancestor.path),
+                excluded: false,
+                path,
+            })
+        });
+    let mut candidates: Vec<Candidate> = recorded.chain(implicit).collect();
+
+    sort_candidates(&mut candidates);
+    let mut seen: HashSet<String> = HashSet::new();
+    candidates.retain(|c| c.excluded || seen.insert(c.path.to_ascii_lowercase()));
+    candidates
+}
+
+/// Candidates in ranking order, checked for project markers only when they
+/// could be next. The project boost multiplies a score by a fixed factor, so
+/// an unchecked candidate cannot overtake a checked one scoring more than
+/// its score times that factor.
+pub struct Ranked<'a> {
+    config: &'a Config,
+    /// Not yet checked, best first, scored without the project boost
+    pending: VecDeque<Candidate>,
+    /// Checked and not project roots, best first
+    plain: VecDeque<Candidate>,
+    /// Checked project roots with the boost applied, best first
+    projects: VecDeque<Candidate>,
+}
+
+impl<'a> Ranked<'a> {
+    fn new(candidates: Vec<Candidate>, config: &'a Config) -> Self {
+        Ranked {
+            config,
+            pending: candidates.into(),
+            plain: VecDeque::new(),
+            projects: VecDeque::new(),
+        }
+    }
+
+    /// The best checked candidate and whether it is a project root
+    fn leader(&self) -> Option<(&Candidate, bool)> {
+        match (self.plain.front(), self.projects.front()) {
+            (Some(plain), Some(project)) if ranking(project, plain) == Ordering::Less => {
+                Some((project, true))
+            }
+            (Some(plain), _) => Some((plain, false)),
+            (None, project) => project.map(|project| (project, true)),
+        }
+    }
+}
+
+impl Iterator for Ranked<'_> {
+    type Item = Candidate;
+
+    fn next(&mut self) -> Option<Candidate> {
+        let boost = project::factor(true, self.config);
+        while let Some(next) = self.pending.front() {
+            if self
+                .leader()
+                .is_some_and(|(leader, _)| leader.score > next.score * boost)
+            {
+                break;
+            }
+            let mut candidate = self.pending.pop_front()?;
+            candidate.project = project::is_root(&candidate.path, self.config);
+            if candidate.project {
+                candidate.score *= boost;
+                self.projects.push_back(candidate);
+            } else {
+                self.plain.push_back(candidate);
+            }
+        }
+        match self.leader()? {
+            (_, true) => self.projects.pop_front(),
+            (_, false) => self.plain.pop_front(),
+        }
+    }
+}
+
+/// Higher score first, breaking ties by the most recent visit
+fn ranking(a: &Candidate, b: &Candidate) -> Ordering {
+    b.score
+        .total_cmp(&a.score)
+        .then_with(|| b.last_visited.cmp(&a.last_visited))
+}
+
+/// Order candidates by score, breaking ties by the most recent visit
+pub fn sort_candidates(candidates: &mut [Candidate]) {
+    candidates.sort_by(ranking);
+}
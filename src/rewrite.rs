@@ -0,0 +1,113 @@
+use std::path::Path;
+
+use crate::{expand_home, Config, PATH_SEP};
+
+/// `path` with `prefix` replaced by `replacement`, if it lies under `prefix`
+fn replace_prefix(path: &str, prefix: &str, replacement: &str) -> Option<String> {
+    let rest = Path::new(path).strip_prefix(prefix).ok()?;
+    if rest.as_os_str().is_empty() {
+        Some(replacement.to_string())
+    } else {
+        Some(format!(
+            "{}{}{}",
+            replacement.trim_end_matches(['/', '\\']),
+            PATH_SEP,
+            rest.to_string_lossy()
+        ))
+    }
+}
+
+/// A leading `~` expanded to this home directory; other paths are unchanged
+pub fn expand_home_prefix(path: &str) -> String {
+    if path == "~" || path.starts_with("~/") || path.starts_with("~\\") {
+        if let Some(expanded) = expand_home(path) {
+            return expanded.to_string_lossy().into_owned();
+        }
+    }
+    path.to_string()
+}
+
+/// Map a path recorded on another machine to this one. A leading `~` is
+/// expanded to this home directory. A path that does not exist here and lies
+/// under a prefix of a `path_rewrites` group is moved under the first other
+/// prefix of that group that does exist; the longest matching prefix wins.
+pub fn localize(path: &str, config: &Config) -> String {
+    let path = expand_home_prefix(path);
+    if config.path_rewrites.is_empty() || Path::new(&path).exists() {
+        return path;
+    }
+
+    let mut matches: Vec<(&String, &Vec<String>)> = config
+        .path_rewrites
+        .iter()
+        .flat_map(|group| group.iter().map(move |prefix| (prefix, group)))
+        .filter(|(prefix, _)| Path::new(&path).starts_with(prefix))
+        .collect();
+    matches.sort_by_key(|(prefix, _)| std::cmp::Reverse(Path::new(prefix).components().count()));
+    for (prefix, group) in matches {
+        let target = group
+            .iter()
+            .filter(|other| *other != prefix)
+            .find(|other| Path::new(other).is_dir());
+        if let Some(rewritten) = target.and_then(|target| replace_prefix(&path, prefix, target)) {
+            return rewritten;
+        }
+    }
+    path
+}
+
+/// `path_rewrites` resolved once for mapping many paths, such as the history
+/// of another machine. Only prefixes missing here are rewritten, each to the
+/// first other prefix of its group that exists; unlike `localize`, the paths
+/// themselves are never checked on disk.
+pub struct Rewriter {
+    /// Missing prefix and its local replacement, longest prefix first
+    rules: Vec<(String, String)>,
+}
+
+impl Rewriter {
+    pub fn new(config: &Config) -> Self {
+        let mut rules: Vec<(String, String)> = Vec::new();
+        for group in &config.path_rewrites {
+            let Some(target) = group.iter().find(|prefix| Path::new(prefix).is_dir()) else {
+                continue;
+            };
+            for prefix in group.iter().filter(|prefix| *prefix != target) {
+                if !Path::new(prefix).is_dir() {
+                    rules.push((prefix.clone(), target.clone()));
+                }
+            }
+        }
+        rules.sort_by_key(|(prefix, _)| std::cmp::Reverse(Path::new(prefix).components().count()));
+        Self { rules }
+    }
+
+    /// `path` with a leading `~` expanded and a missing prefix replaced
+    pub fn apply(&self, path: &str) -> String {
+        let path = expand_home_prefix(path);
+        self.rules
+            .iter()
+            .find_map(|(prefix, target)| replace_prefix(&path, prefix, target))
+            .unwrap_or(path)
+    }
+}
+
+/// A path under the home directory written as `~/...`, so it reads the same
+/// on machines whose home directories differ
+pub fn portable(path: &str) -> String {
+    match dirs::home_dir() {
+        Some(home) => {
+            replace_prefix(path, &home.to_string_lossy(), "~").unwrap_or_else(|| path.to_string())
+        }
+        None => path.to_string(),
+    }
+}
+
+/// How an alias target is stored: `~`-relative when `home_relative_aliases` is set
+pub fn alias_path(path: &str, config: &Config) -> String {
+    if config.home_relative_aliases {
+        portable(path)
+    } else {
+        path.to_string()
+    }
+}
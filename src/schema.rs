@@ -0,0 +1,68 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+
+use crate::{Aliases, AppState, Config};
+
+/// A persisted file with a `version` key and an ordered migration chain.
+/// Files without a `version` key are version 0.
+pub trait Versioned: Serialize + DeserializeOwned + Default {
+    /// `MIGRATIONS[i]` upgrades a document from version `i` to `i + 1`
+    const MIGRATIONS: &'static [fn(&mut Value)];
+    /// Schema version written by this binary
+    const VERSION: u32 = Self::MIGRATIONS.len() as u32;
+}
+
+impl Versioned for AppState {
+    const MIGRATIONS: &'static [fn(&mut Value)] = &[add_version_key, add_missing_since];
+}
+
+impl Versioned for Aliases {
+    const MIGRATIONS: &'static [fn(&mut Value)] = &[nest_alias_map];
+}
+
+impl Versioned for Config {
+    const MIGRATIONS: &'static [fn(&mut Value)] = &[add_version_key];
+}
+
+/// v0 -> v1 for files whose layout did not change; only the key is added
+fn add_version_key(_value: &mut Value) {}
+
+/// v1 -> v2 for state.json: history entries gain an optional `missing_since`.
+/// Existing files need no change, but older binaries must not drop the field.
+fn add_missing_since(_value: &mut Value) {}
+
+/// v0 -> v1 for aliases.json: the flat `{name: path}` map moves under
+/// `"aliases"` so that a top-level `version` key cannot collide with an alias
+fn nest_alias_map(value: &mut Value) {
+    let map = value.take();
+    *value = json!({ "aliases": map });
+}
+
+/// Schema version of a parsed document
+pub fn version_of(value: &Value) -> u32 {
+    value
+        .get("version")
+        .and_then(Value::as_u64)
+        .map(|v| v as u32)
+        .unwrap_or(0)
+}
+
+/// Run the migrations needed to bring a document up to `T::VERSION`.
+/// Documents from a newer schema are left untouched.
+pub fn migrate<T: Versioned>(value: &mut Value) {
+    let from = version_of(value) as usize;
+    for migration in T::MIGRATIONS.iter().skip(from) {
+        migration(value);
+    }
+}
+
+/// Serialize a value together with the current `version` key
+pub fn to_versioned_value<T: Versioned>(value: &T) -> serde_json::Result<Value> {
+    let mut object = Map::new();
+    object.insert("version".to_string(), json!(T::VERSION));
+    if let Value::Object(fields) = serde_json::to_value(value)? {
+        object.extend(fields.into_iter().filter(|(key, _)| key != "version"));
+    }
+    Ok(Value::Object(object))
+}
@@ -0,0 +1,359 @@
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use crate::import::Imported;
+
+/// A shell history file layout
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shell {
+    /// One command per line, optionally preceded by `#<epoch>` lines
+    Bash,
+    /// Extended history: `: <epoch>:<duration>;<command>`
+    Zsh,
+    /// `- cmd: <command>` entries followed by `when: <epoch>`
+    Fish,
+}
+
+/// One command read from a history file
+struct Command {
+    line: String,
+    time: Option<DateTime<Utc>>,
+}
+
+/// Directories changed to in one history file
+pub struct Visits {
+    /// Commands read
+    pub commands: usize,
+    /// Targets that could be resolved to an absolute path
+    pub targets: usize,
+    pub entries: Vec<Imported>,
+}
+
+/// The usual history files of bash, zsh and fish that exist
+pub fn default_files() -> Vec<PathBuf> {
+    let home = dirs::home_dir();
+    let candidates = [
+        home.as_ref().map(|h| h.join(".bash_history")),
+        home.as_ref().map(|h| h.join(".zsh_history")),
+        home.as_ref().map(|h| h.join(".histfile")),
+        dirs::data_dir().map(|d| d.join("fish").join("fish_history")),
+    ];
+    candidates
+        .into_iter()
+        .flatten()
+        .filter(|path| path.is_file())
+        .collect()
+}
+
+/// Guess the layout from the first non-empty line
+fn detect(text: &str) -> Shell {
+    let first = text
+        .lines()
+        .find(|line| !line.trim().is_empty())
+        .unwrap_or("");
+    if first.starts_with("- cmd: ") {
+        Shell::Fish
+    } else if first.starts_with(": ") && first.contains(';') {
+        Shell::Zsh
+    } else {
+        Shell::Bash
+    }
+}
+
+/// zsh stores bytes 0x83-0x9f and 0xa0 as 0x83 followed by the byte xor 32
+fn unmetafy(bytes: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut iter = bytes.iter();
+    while let Some(&b) = iter.next() {
+        if b == 0x83 {
+            if let Some(&next) = iter.next() {
+                out.push(next ^ 32);
+            }
+        } else {
+            out.push(b);
+        }
+    }
+    out
+}
+
+fn from_epoch(secs: &str) -> Option<DateTime<Utc>> {
+    DateTime::from_timestamp(secs.trim().parse().ok()?, 0)
+}
+
+fn parse_bash(text: &str) -> Vec<Command> {
+    let mut commands = Vec::new();
+    let mut time = None;
+    for line in text.lines() {
+        // Written before each command when HISTTIMEFORMAT is set
+        if let Some(stamp) = line.strip_prefix('#').and_then(from_epoch) {
+            time = Some(stamp);
+            continue;
+        }
+        commands.push(Command {
+            line: line.to_string(),
+            time: time.take(),
+        });
+    }
+    commands
+}
+
+fn parse_zsh(text: &str) -> Vec<Command> {
+    text.lines()
+        .map(|line| {
+            match line
+                .strip_prefix(": ")
+                .and_then(|rest| rest.split_once(';'))
+            {
+                Some((meta, command)) => Command {
+                    line: command.to_string(),
+                    time: meta.split(':').next().and_then(from_epoch),
+                },
+                None => Command {
+                    line: line.to_string(),
+                    time: None,
+                },
+            }
+        })
+        .collect()
+}
+
+fn parse_fish(text: &str) -> Vec<Command> {
+    let mut commands: Vec<Command> = Vec::new();
+    for line in text.lines() {
+        if let Some(command) = line.strip_prefix("- cmd: ") {
+            commands.push(Command {
+                line: command.replace("\\n", "\n").replace("\\\\", "\\"),
+                time: None,
+            });
+        } else if let Some(stamp) = line.trim_start().strip_prefix("when: ") {
+            if let Some(last) = commands.last_mut() {
+                last.time = from_epoch(stamp);
+            }
+        }
+    }
+    commands
+}
+
+/// A word with anything the shell would expand, other than `~` and `$HOME`,
+/// cannot be resolved from the history alone
+fn literal_word(word: String) -> Option<String> {
+    let word = word
+        .strip_prefix("$HOME")
+        .or_else(|| word.strip_prefix("${HOME}"))
+        .map(|rest| format!("~{}", rest))
+        .unwrap_or(word);
+    (!word.contains(['$', '`', '*', '?', '[', '{'])).then_some(word)
+}
+
+/// Split a command line into simple commands at `;`, `&`, `|`, parentheses
+/// and newlines, and each into words with quotes and backslashes removed.
+/// Words that need expanding are `None`.
+fn simple_commands(line: &str) -> Vec<Vec<Option<String>>> {
+    let mut commands = Vec::new();
+    let mut words: Vec<Option<String>> = Vec::new();
+    let mut word: Option<String> = None;
+    let mut quote: Option<char> = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('"'), '\\') | (None, '\\') => {
+                if let Some(next) = chars.next() {
+                    word.get_or_insert_with(String::new).push(next);
+                }
+            }
+            (Some(_), c) => word.get_or_insert_with(String::new).push(c),
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                word.get_or_insert_with(String::new);
+            }
+            (None, ' ' | '\t') => words.extend(word.take().map(literal_word)),
+            (None, ';' | '&' | '|' | '\n' | '(' | ')') => {
+                words.extend(word.take().map(literal_word));
+                if !words.is_empty() {
+                    commands.push(std::mem::take(&mut words));
+                }
+            }
+            (None, c) => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word.take().map(literal_word));
+    if !words.is_empty() {
+        commands.push(words);
+    }
+    commands
+}
+
+/// Resolve `.` and `..` without touching the filesystem
+fn clean(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                out.pop();
+            }
+            other => out.push(other),
+        }
+    }
+    out
+}
+
+/// Tracks the working directory through a history file
+struct Tracker {
+    home: Option<PathBuf>,
+    /// `None` while the directory cannot be told from the history
+    cwd: Option<PathBuf>,
+    previous: Option<PathBuf>,
+}
+
+impl Tracker {
+    /// Where `cd <args>` leads, if it can be told from the history alone
+    fn resolve(&self, args: &[Option<String>]) -> Option<PathBuf> {
+        // Skip options such as `cd -P`, but `-` itself means the previous directory
+        let mut args = args.iter().skip_while(|arg| {
+            arg.as_deref()
+                .is_some_and(|a| a.starts_with('-') && a != "-")
+        });
+        let target = match args.next() {
+            None => return self.home.clone(),
+            Some(None) => return None,
+            Some(Some(target)) => target,
+        };
+        if target == "-" {
+            return self.previous.clone();
+        }
+        let path = if target == "~" {
+            self.home.clone()?
+        } else if let Some(rest) = target.strip_prefix("~/") {
+            self.home.as_ref()?.join(rest)
+        } else if target.starts_with('~') {
+            // Another user's home directory
+            return None;
+        } else if Path::new(target).is_absolute() {
+            PathBuf::from(target)
+        } else {
+            self.cwd.as_ref()?.join(target)
+        };
+        Some(clean(&path))
+    }
+
+    fn change_to(&mut self, target: Option<PathBuf>) {
+        self.previous = self.cwd.take();
+        self.cwd = target;
+    }
+
+    /// Follow one simple command, returning the directory it changed to
+    /// when that is known
+    fn step(&mut self, name: &str, args: &[Option<String>]) -> Option<PathBuf> {
+        let first = args.first().map(|arg| arg.as_deref());
+        let target = match name {
+            "cd" => self.resolve(args),
+            // Without arguments pushd swaps the top two directories
+            "pushd" if args.is_empty() => self.previous.clone(),
+            "pushd" if first.flatten().is_some_and(|a| a.starts_with(['+', '-'])) => None,
+            "pushd" => self.resolve(args),
+            "popd" => None,
+            // Other j options (-l, -c, --doctor, ...) do not change directory
+            "j" if first
+                .flatten()
+                .is_some_and(|a| a.starts_with('-') && a != "-" && !is_j_jump(a)) =>
+            {
+                return None;
+            }
+            "j" => {
+                let keyword = match args {
+                    [] | [_] => args.to_vec(),
+                    // j joins its keywords into one relative path
+                    _ if args.iter().all(Option::is_some) => {
+                        let words: Vec<&str> = args.iter().flatten().map(String::as_str).collect();
+                        vec![Some(words.join("/"))]
+                    }
+                    _ => vec![None],
+                };
+                self.resolve(&keyword)
+            }
+            _ => return None,
+        };
+        // A missing target either failed back then or was removed since; for
+        // j it may also have been a keyword looked up in j's own history.
+        // Either way the directory reached is unknown.
+        let target = target.filter(|path| path.is_dir());
+        self.change_to(target.clone());
+        target
+    }
+}
+
+/// j options that jump somewhere the history does not tell
+fn is_j_jump(option: &str) -> bool {
+    matches!(option, "-i" | "--interactive" | "-p" | "--project")
+        || option[1..].chars().all(|c| c.is_ascii_digit())
+}
+
+/// Read a bash, zsh or fish history file and collect every existing
+/// directory that `cd`, `pushd` or `j` changed to. Relative targets are
+/// resolved against the directory reached by the preceding commands; after
+/// a target that cannot be resolved (a variable, a glob, a directory that no
+/// longer exists) relative targets are dropped until the next absolute one.
+/// Commands without a timestamp count as run when the file was last written.
+pub fn read(path: &Path) -> io::Result<Visits> {
+    let modified = fs::metadata(path)?
+        .modified()
+        .map(DateTime::<Utc>::from)
+        .unwrap_or_else(|_| Utc::now());
+    let bytes = fs::read(path)?;
+    let text = String::from_utf8_lossy(&bytes);
+    let commands = match detect(&text) {
+        Shell::Bash => parse_bash(&text),
+        Shell::Zsh => parse_zsh(&String::from_utf8_lossy(&unmetafy(&bytes))),
+        Shell::Fish => parse_fish(&text),
+    };
+
+    let mut tracker = Tracker {
+        home: dirs::home_dir(),
+        cwd: None,
+        previous: None,
+    };
+    let mut targets = 0;
+    let mut found: Vec<Imported> = Vec::new();
+    let mut seen: HashMap<PathBuf, usize> = HashMap::new();
+    for command in &commands {
+        for words in simple_commands(&command.line) {
+            // `builtin cd` and `command cd` run the same command
+            let words = match words.first() {
+                Some(Some(first)) if first == "builtin" || first == "command" => &words[1..],
+                _ => &words[..],
+            };
+            let Some((Some(name), args)) = words.split_first() else {
+                continue;
+            };
+            let Some(target) = tracker.step(name, args) else {
+                continue;
+            };
+            targets += 1;
+            let time = command.time.unwrap_or(modified);
+            match seen.get(&target) {
+                Some(&i) => {
+                    found[i].visit_count += 1;
+                    found[i].last_visited = found[i].last_visited.max(time);
+                }
+                None => {
+                    seen.insert(target.clone(), found.len());
+                    found.push(Imported {
+                        path: target.to_string_lossy().into_owned(),
+                        visit_count: 1,
+                        last_visited: time,
+                    });
+                }
+            }
+        }
+    }
+    Ok(Visits {
+        commands: commands.len(),
+        targets,
+        entries: found,
+    })
+}
@@ -0,0 +1,495 @@
+use chrono::{Duration, Utc};
+use std::collections::HashMap;
+use serde_json::Value;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use super::{
+    added_visits, get_aliases_path, get_config_path, get_state_path, index_by_path, path_key,
+    removed_here, Store,
+};
+use crate::aging;
+use crate::schema::{self, Versioned};
+use crate::{Aliases, AppState, Config, HistoryEntry};
+
+/// Number of rolling backups kept per file
+const MAX_BACKUPS: usize = 5;
+/// Minimum time between two backups of the same file
+const BACKUP_INTERVAL_HOURS: i64 = 24;
+
+/// Append a suffix to a file name, e.g. state.json -> state.json.lock
+fn sibling_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+/// Advisory lock on `<file>.lock`, released when the returned handle is dropped.
+/// A separate lock file is used because the data file itself is replaced by rename.
+fn lock(path: &Path, exclusive: bool) -> io::Result<File> {
+    let lock_file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(sibling_path(path, ".lock"))?;
+    if exclusive {
+        lock_file.lock()?;
+    } else {
+        lock_file.lock_shared()?;
+    }
+    Ok(lock_file)
+}
+
+/// Write to a temporary file in the same directory, then rename it over the target
+/// so readers never observe a partially written file.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let tmp_path = sibling_path(path, &format!(".tmp-{}", std::process::id()));
+    let result = (|| {
+        let mut file = File::create(&tmp_path)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+        fs::rename(&tmp_path, path)
+    })();
+    if result.is_err() {
+        fs::remove_file(&tmp_path).ok();
+    }
+    result
+}
+
+/// Why a persisted file could not be read
+#[derive(Debug)]
+pub enum ReadError {
+    Io(io::Error),
+    Corrupt(serde_json::Error),
+    /// Written by a newer j in a layout this one cannot read; the file
+    /// itself is fine and must be left alone
+    Newer { version: u32, supported: u32 },
+}
+
+impl fmt::Display for ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReadError::Io(err) => write!(f, "{}", err),
+            ReadError::Corrupt(err) => write!(f, "invalid JSON: {}", err),
+            ReadError::Newer { version, supported } => write!(
+                f,
+                "uses schema v{}, newer than this j (v{}), in a layout it cannot read",
+                version, supported
+            ),
+        }
+    }
+}
+
+fn read_value(path: &Path) -> Result<Option<Value>, ReadError> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(ReadError::Io(err)),
+    };
+    serde_json::from_str(&contents)
+        .map(Some)
+        .map_err(ReadError::Corrupt)
+}
+
+/// Schema version of a file, or `None` if it is missing or unreadable
+pub fn file_version(path: &Path) -> Option<u32> {
+    read_value(path).ok().flatten().map(|value| schema::version_of(&value))
+}
+
+/// Read a file, migrating it to the current schema in memory.
+/// Also returns the schema version found on disk. A file from a newer
+/// schema is still read if its layout is compatible.
+fn read_versioned<T: Versioned>(path: &Path) -> Result<Option<(T, u32)>, ReadError> {
+    let mut value = match read_value(path)? {
+        Some(value) => value,
+        None => return Ok(None),
+    };
+    let version = schema::version_of(&value);
+    if version > T::VERSION {
+        return serde_json::from_value(value)
+            .map(|parsed| Some((parsed, version)))
+            .map_err(|_| ReadError::Newer {
+                version,
+                supported: T::VERSION,
+            });
+    }
+    schema::migrate::<T>(&mut value);
+    serde_json::from_value(value)
+        .map(|parsed| Some((parsed, version)))
+        .map_err(ReadError::Corrupt)
+}
+
+/// Read and parse a JSON file. Returns `Ok(None)` if the file does not exist.
+pub fn try_read_json<T: Versioned>(path: &Path) -> Result<Option<T>, ReadError> {
+    read_versioned(path).map(|parsed| parsed.map(|(value, _)| value))
+}
+
+/// Read a JSON file, falling back to the default value if it is missing.
+/// A corrupt file is quarantined and the newest readable backup is restored.
+/// A file from a newer j that cannot be read gives the default in memory
+/// only; `write_json` refuses to replace it and `j --doctor` reports it.
+/// The flag is set when the file uses an older schema and should be rewritten.
+fn read_json<T: Versioned>(path: &Path) -> (T, bool) {
+    match read_versioned::<T>(path) {
+        Ok(Some((value, version))) => (value, version < T::VERSION),
+        Ok(None) => (T::default(), false),
+        Err(ReadError::Newer { .. }) => (T::default(), false),
+        Err(ReadError::Corrupt(err)) => {
+            quarantine(path, &err);
+            (restore_backup(path).unwrap_or_default(), false)
+        }
+        Err(err) => {
+            eprintln!("j: cannot read {}: {}", path.display(), err);
+            (T::default(), false)
+        }
+    }
+}
+
+/// Move a corrupt file aside as `<file>.corrupt-<timestamp>` so it is never overwritten
+fn quarantine(path: &Path, err: &serde_json::Error) {
+    let timestamp = Utc::now().format("%Y%m%d%H%M%S");
+    let target = sibling_path(path, &format!(".corrupt-{}", timestamp));
+    match fs::rename(path, &target) {
+        Ok(()) => eprintln!(
+            "j: {} is corrupt ({}); moved to {}",
+            path.display(),
+            err,
+            target.display()
+        ),
+        Err(rename_err) => eprintln!(
+            "j: {} is corrupt ({}) and could not be moved aside: {}",
+            path.display(),
+            err,
+            rename_err
+        ),
+    }
+}
+
+/// Files left behind by `quarantine`
+pub fn quarantined_files(path: &Path) -> Vec<PathBuf> {
+    let prefix = format!(
+        "{}.corrupt-",
+        path.file_name().unwrap_or_default().to_string_lossy()
+    );
+    let mut files: Vec<PathBuf> = path
+        .parent()
+        .and_then(|dir| fs::read_dir(dir).ok())
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with(&prefix))
+        .map(|entry| entry.path())
+        .collect();
+    files.sort();
+    files
+}
+
+fn backup_dir(path: &Path) -> PathBuf {
+    path.with_file_name("backups")
+}
+
+/// Backups of a file, newest first
+pub fn list_backups(path: &Path) -> Vec<PathBuf> {
+    let prefix = format!(
+        "{}.",
+        path.file_name().unwrap_or_default().to_string_lossy()
+    );
+    let mut backups: Vec<PathBuf> = fs::read_dir(backup_dir(path))
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with(&prefix))
+        .map(|entry| entry.path())
+        .collect();
+    backups.sort();
+    backups.reverse();
+    backups
+}
+
+/// Copy the current file into the backup directory, keeping at most
+/// MAX_BACKUPS copies. Unless `force` is set, nothing is copied while the
+/// newest backup is younger than BACKUP_INTERVAL_HOURS.
+fn rotate_backups(path: &Path, force: bool) -> io::Result<()> {
+    let backups = list_backups(path);
+    let is_recent = backups
+        .first()
+        .and_then(|newest| fs::metadata(newest).ok())
+        .and_then(|meta| meta.modified().ok())
+        .and_then(|modified| modified.elapsed().ok())
+        .and_then(|elapsed| Duration::from_std(elapsed).ok())
+        .is_some_and(|elapsed| elapsed < Duration::hours(BACKUP_INTERVAL_HOURS));
+    if is_recent && !force {
+        return Ok(());
+    }
+
+    let dir = backup_dir(path);
+    fs::create_dir_all(&dir)?;
+    let timestamp = Utc::now().format("%Y%m%d%H%M%S");
+    let name = format!(
+        "{}.{}",
+        path.file_name().unwrap_or_default().to_string_lossy(),
+        timestamp
+    );
+    fs::copy(path, dir.join(name))?;
+
+    for old in list_backups(path).into_iter().skip(MAX_BACKUPS) {
+        fs::remove_file(old).ok();
+    }
+    Ok(())
+}
+
+/// Restore the newest backup that parses, writing it back in place
+fn restore_backup<T: Versioned>(path: &Path) -> Option<T> {
+    for backup in list_backups(path) {
+        if let Ok(Some(value)) = try_read_json::<T>(&backup) {
+            eprintln!(
+                "j: restored {} from backup {}",
+                path.display(),
+                backup.display()
+            );
+            write_json(path, &value).ok();
+            return Some(value);
+        }
+    }
+    None
+}
+
+/// Write a file with the current schema version.
+/// A file written by a newer j is never overwritten, and a file from an
+/// older schema is always backed up before it is upgraded.
+fn write_json<T: Versioned>(path: &Path, value: &T) -> io::Result<()> {
+    write_versioned::<T>(path, schema::to_versioned_value(value)?)
+}
+
+/// `write_json` for a document already serialized with its `version` key
+fn write_versioned<T: Versioned>(path: &Path, value: Value) -> io::Result<()> {
+    if let Some(version) = file_version(path) {
+        if version > T::VERSION {
+            return Err(io::Error::other(format!(
+                "{} uses schema version {}, newer than the supported {}; refusing to overwrite it",
+                path.display(),
+                version,
+                T::VERSION
+            )));
+        }
+        rotate_backups(path, version < T::VERSION).ok();
+    }
+    let json = serde_json::to_string_pretty(&value)?;
+    write_atomic(path, json.as_bytes())
+}
+
+/// Config as written to disk: the keys already in the file and those that
+/// differ from the defaults. Settings never written keep following the
+/// defaults of whichever j reads them.
+fn sparse_config(config: &Config, existing: Option<&Value>) -> serde_json::Result<Value> {
+    let mut value = schema::to_versioned_value(config)?;
+    let defaults = serde_json::to_value(Config::default())?;
+    if let Value::Object(fields) = &mut value {
+        fields.retain(|key, field| {
+            key == "version"
+                || defaults.get(key) != Some(field)
+                || existing.is_some_and(|existing| existing.get(key).is_some())
+        });
+    }
+    Ok(value)
+}
+
+/// Three-way merge of history.
+/// `base` is what this process loaded, `ours` is what it wants to write and
+/// `disk` is what is on disk now. Visits added by this process are added on
+/// top of the disk counts, and entries removed by this process are removed
+/// from the disk version; everything else another shell did is kept.
+fn merge_history(
+    base: &[HistoryEntry],
+    ours: &[HistoryEntry],
+    disk: &[HistoryEntry],
+) -> Vec<HistoryEntry> {
+    let base = index_by_path(base);
+    let ours_index = index_by_path(ours);
+    let mut merged: Vec<HistoryEntry> = disk
+        .iter()
+        .filter(|d| !removed_here(&base, &ours_index, &d.path))
+        .cloned()
+        .collect();
+    let mut positions: HashMap<String, usize> = HashMap::with_capacity(merged.len());
+    for (i, entry) in merged.iter().enumerate() {
+        positions.entry(path_key(&entry.path)).or_insert(i);
+    }
+
+    for entry in ours {
+        let key = path_key(&entry.path);
+        let in_base = base.contains_key(&key);
+        let added = added_visits(&base, entry);
+
+        match positions.get(&key).map(|&i| &mut merged[i]) {
+            Some(target) => {
+                target.visit_count = target.visit_count.saturating_add(added);
+                target.last_visited = target.last_visited.max(entry.last_visited);
+            }
+            // Removed by another shell; keep it only if this process visited it again
+            None if in_base && added == 0 => {}
+            None => {
+                positions.insert(key, merged.len());
+                merged.push(HistoryEntry {
+                    visit_count: added.max(1),
+                    ..entry.clone()
+                });
+            }
+        }
+    }
+
+    merged
+}
+
+/// History, aliases and config as pretty-printed JSON files
+pub struct JsonStore;
+
+impl Store for JsonStore {
+    fn load_state(&self) -> AppState {
+        let path = match get_state_path() {
+            Ok(path) => path,
+            Err(_) => return AppState::default(),
+        };
+        let read_lock = lock(&path, false);
+        let (mut state, outdated) = read_json::<AppState>(&path);
+        drop(read_lock);
+        state.loaded_history = state.history.clone();
+        if outdated {
+            self.save_state(&mut state).ok();
+        }
+        state
+    }
+
+    /// Save state under an exclusive lock. History changed on disk by another
+    /// shell since `load_state` is merged rather than overwritten, and the
+    /// merged result gets an aging pass before it is written.
+    fn save_state(&self, state: &mut AppState) -> io::Result<()> {
+        let path = get_state_path()?;
+        let _lock = lock(&path, true)?;
+
+        let disk: AppState = match try_read_json(&path) {
+            Ok(disk) => disk.unwrap_or_default(),
+            // Our in-memory copy is the best data left; keep the broken file aside
+            Err(ReadError::Corrupt(err)) => {
+                quarantine(&path, &err);
+                AppState::default()
+            }
+            Err(err) => return Err(io::Error::other(format!("{}: {}", path.display(), err))),
+        };
+        state.history = merge_history(&state.loaded_history, &state.history, &disk.history);
+        let dropped = aging::age_history(&mut state.history, &self.load_config());
+        state.aged_out = disk.aged_out;
+        state.aged_out.add(&dropped);
+
+        write_json(&path, state)?;
+        state.loaded_history = state.history.clone();
+        Ok(())
+    }
+
+    fn load_aliases(&self) -> Aliases {
+        let path = match get_aliases_path() {
+            Ok(path) => path,
+            Err(_) => return Aliases::default(),
+        };
+        let (aliases, outdated) = read_json::<Aliases>(&path);
+        if outdated {
+            self.save_aliases(&aliases).ok();
+        }
+        aliases
+    }
+
+    fn save_aliases(&self, aliases: &Aliases) -> io::Result<()> {
+        let path = get_aliases_path()?;
+        let _lock = lock(&path, true)?;
+        write_json(&path, aliases)
+    }
+
+    fn load_config(&self) -> Config {
+        let path = match get_config_path() {
+            Ok(path) => path,
+            Err(_) => return Config::default(),
+        };
+        let (mut config, outdated) = read_json::<Config>(&path);
+        if outdated {
+            self.save_config(&config).ok();
+        }
+        // Problems are reported by `j --doctor`
+        config.sanitize();
+        config
+    }
+
+    fn save_config(&self, config: &Config) -> io::Result<()> {
+        let path = get_config_path()?;
+        let _lock = lock(&path, true)?;
+        let existing = read_value(&path).ok().flatten();
+        write_versioned::<Config>(&path, sparse_config(config, existing.as_ref())?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, TimeZone, Utc};
+
+    use super::merge_history;
+    use crate::{trim_history, Config, EvictionPolicy, HistoryEntry};
+
+    fn day(n: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2026, 1, n, 0, 0, 0).unwrap()
+    }
+
+    fn entry(path: &str, visit_count: u32, last_visited: DateTime<Utc>) -> HistoryEntry {
+        HistoryEntry {
+            path: path.to_string(),
+            last_visited,
+            visit_count,
+            missing_since: None,
+        }
+    }
+
+    fn counts(history: &[HistoryEntry]) -> Vec<(&str, u32)> {
+        history
+            .iter()
+            .map(|entry| (entry.path.as_str(), entry.visit_count))
+            .collect()
+    }
+
+    #[test]
+    fn added_visit_goes_on_top_of_another_shells_visits() {
+        let base = vec![entry("/a", 1, day(1))];
+        let ours = vec![entry("/a", 2, day(3))];
+        let disk = vec![entry("/a", 3, day(2))];
+
+        let merged = merge_history(&base, &ours, &disk);
+        assert_eq!(counts(&merged), [("/a", 4)]);
+        assert_eq!(merged[0].last_visited, day(3));
+    }
+
+    #[test]
+    fn removal_here_wins_over_a_visit_elsewhere() {
+        let base = vec![entry("/a", 1, day(1)), entry("/b", 1, day(1))];
+        let ours = vec![entry("/b", 1, day(1))];
+        let disk = vec![entry("/a", 2, day(2)), entry("/b", 1, day(1))];
+
+        assert_eq!(counts(&merge_history(&base, &ours, &disk)), [("/b", 1)]);
+    }
+
+    #[test]
+    fn eviction_by_trim_history_is_kept() {
+        let config = Config {
+            max_history: 2,
+            eviction: EvictionPolicy::Lru,
+            ..Config::default()
+        };
+        let base = vec![entry("/a", 1, day(1)), entry("/b", 1, day(2))];
+        let mut ours = base.clone();
+        ours.push(entry("/c", 1, day(3)));
+        trim_history(&mut ours, &config);
+
+        assert_eq!(
+            counts(&merge_history(&base, &ours, &base)),
+            [("/b", 1), ("/c", 1)]
+        );
+    }
+}
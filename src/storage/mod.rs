@@ -0,0 +1,295 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use crate::{paths_equal, Aliases, AppState, Config, HistoryEntry};
+
+pub mod json;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod sync;
+
+/// Persistence for history, aliases and config
+pub trait Store: Send + Sync {
+    fn load_state(&self) -> AppState;
+    /// Save state, merging history changed by other shells since `load_state`
+    /// and running an aging pass over the result
+    fn save_state(&self, state: &mut AppState) -> io::Result<()>;
+    /// Record one visit to `path`, as `j -c` does after every `cd`
+    fn record_visit(&self, path: &str, config: &Config) -> io::Result<()> {
+        let mut state = self.load_state();
+        crate::add_to_history(&mut state, path, config);
+        self.save_state(&mut state)
+    }
+    /// Remove `path` from history. Returns whether it was there.
+    fn forget(&self, path: &str) -> io::Result<bool> {
+        let mut state = self.load_state();
+        let before = state.history.len();
+        state.history.retain(|entry| !paths_equal(&entry.path, path));
+        if state.history.len() == before {
+            return Ok(false);
+        }
+        self.save_state(&mut state)?;
+        Ok(true)
+    }
+    fn load_aliases(&self) -> Aliases;
+    fn save_aliases(&self, aliases: &Aliases) -> io::Result<()>;
+    fn load_config(&self) -> Config;
+    fn save_config(&self, config: &Config) -> io::Result<()>;
+}
+
+/// Storage backend selected by `"storage"` in config.json
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageKind {
+    #[default]
+    Json,
+    Sqlite,
+}
+
+/// Read a directory from an environment variable, ignoring empty values
+fn env_dir(var: &str) -> Option<PathBuf> {
+    env::var_os(var).filter(|v| !v.is_empty()).map(PathBuf::from)
+}
+
+/// Resolve a j directory from an explicit override, an XDG base directory,
+/// or a default location under the home directory. A relative override is
+/// an error, since it would point somewhere else in every directory.
+fn resolve_dir(override_var: &str, xdg_var: &str, home_default: &[&str]) -> io::Result<PathBuf> {
+    let dir = if let Some(dir) = env_dir(override_var) {
+        if !dir.is_absolute() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} must be an absolute path, not {}", override_var, dir.display()),
+            ));
+        }
+        dir
+    } else if let Some(base) = env_dir(xdg_var).filter(|p| p.is_absolute()) {
+        base.join("j")
+    } else {
+        let home = dirs::home_dir().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("cannot determine home directory; set {}", override_var),
+            )
+        })?;
+        home_default
+            .iter()
+            .fold(home, |path, part| path.join(part))
+            .join("j")
+    };
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+/// Directory for aliases.json and config.json
+pub fn get_config_dir() -> io::Result<PathBuf> {
+    resolve_dir("J_CONFIG_DIR", "XDG_CONFIG_HOME", &[".config"])
+}
+
+/// Directory for state.json (history and undo/redo stacks)
+pub fn get_data_dir() -> io::Result<PathBuf> {
+    resolve_dir("J_DATA_DIR", "XDG_DATA_HOME", &[".local", "share"])
+}
+
+/// Copy a file from the pre-XDG location (~/.config/j) into its new directory.
+/// Directories given by an override variable (`J_DATA_DIR`, `J_CONFIG_DIR`)
+/// are left alone, so a sandbox never picks up the real files. The original
+/// is kept, with a `<file>.migrated` marker next to it so that deleting the
+/// new directory to start over does not bring the old file back.
+fn migrate_legacy_file(dir: &Path, file_name: &str, override_var: &str) {
+    if env_dir(override_var).is_some() {
+        return;
+    }
+    let legacy_dir = match dirs::home_dir() {
+        Some(home) => home.join(".config").join("j"),
+        None => return,
+    };
+    let legacy = legacy_dir.join(file_name);
+    let marker = legacy_dir.join(format!("{}.migrated", file_name));
+    if legacy_dir == dir || !legacy.is_file() || marker.exists() {
+        return;
+    }
+    let target = dir.join(file_name);
+    if !target.exists() && fs::copy(&legacy, &target).is_err() {
+        return;
+    }
+    fs::write(&marker, format!("{}\n", target.display())).ok();
+}
+
+pub fn get_state_path() -> io::Result<PathBuf> {
+    let dir = get_data_dir()?;
+    migrate_legacy_file(&dir, "state.json", "J_DATA_DIR");
+    Ok(dir.join("state.json"))
+}
+
+/// Directory index built by `j --index update`, kept next to state.json
+pub fn get_index_path() -> io::Result<PathBuf> {
+    Ok(get_data_dir()?.join("index"))
+}
+
+pub fn get_aliases_path() -> io::Result<PathBuf> {
+    let dir = get_config_dir()?;
+    migrate_legacy_file(&dir, "aliases.json", "J_CONFIG_DIR");
+    Ok(dir.join("aliases.json"))
+}
+
+pub fn get_config_path() -> io::Result<PathBuf> {
+    let dir = get_config_dir()?;
+    migrate_legacy_file(&dir, "config.json", "J_CONFIG_DIR");
+    Ok(dir.join("config.json"))
+}
+
+/// Key under which `paths_equal` paths collide
+pub fn path_key(path: &str) -> String {
+    path.to_ascii_lowercase()
+}
+
+/// History entries by `path_key`, so a merge does one lookup per entry
+/// instead of scanning the whole history. The first of duplicate paths wins.
+fn index_by_path(history: &[HistoryEntry]) -> HashMap<String, &HistoryEntry> {
+    let mut index = HashMap::with_capacity(history.len());
+    for entry in history {
+        index.entry(path_key(&entry.path)).or_insert(entry);
+    }
+    index
+}
+
+/// Visits this process added to an entry since `base` was loaded
+fn added_visits(base: &HashMap<String, &HistoryEntry>, entry: &HistoryEntry) -> u32 {
+    match base.get(&path_key(&entry.path)) {
+        Some(b) => entry.visit_count.saturating_sub(b.visit_count),
+        None => entry.visit_count,
+    }
+}
+
+/// Whether this process removed a path that was present in `base`
+fn removed_here(
+    base: &HashMap<String, &HistoryEntry>,
+    ours: &HashMap<String, &HistoryEntry>,
+    path: &str,
+) -> bool {
+    let key = path_key(path);
+    base.contains_key(&key) && !ours.contains_key(&key)
+}
+
+/// Stands in for a backend that could not be opened. Reads are empty and
+/// writes fail, so nothing is recorded in another backend that would never
+/// be read back once the configured one works again.
+#[cfg(feature = "sqlite")]
+struct Unavailable {
+    error: String,
+}
+
+#[cfg(feature = "sqlite")]
+impl Unavailable {
+    fn refuse(&self) -> io::Error {
+        io::Error::other(self.error.clone())
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl Store for Unavailable {
+    fn load_state(&self) -> AppState {
+        AppState::default()
+    }
+
+    fn save_state(&self, _state: &mut AppState) -> io::Result<()> {
+        Err(self.refuse())
+    }
+
+    fn load_aliases(&self) -> Aliases {
+        Aliases::default()
+    }
+
+    fn save_aliases(&self, _aliases: &Aliases) -> io::Result<()> {
+        Err(self.refuse())
+    }
+
+    fn load_config(&self) -> Config {
+        json::JsonStore.load_config()
+    }
+
+    fn save_config(&self, config: &Config) -> io::Result<()> {
+        json::JsonStore.save_config(config)
+    }
+}
+
+/// The configured backend. Config itself always lives in config.json so the
+/// backend can be chosen before anything else is opened. A database that
+/// cannot be opened is reported once per run; `j --doctor` reports it too.
+pub fn store() -> &'static dyn Store {
+    static STORE: OnceLock<Box<dyn Store>> = OnceLock::new();
+    STORE
+        .get_or_init(|| match json::JsonStore.load_config().storage {
+            #[cfg(feature = "sqlite")]
+            StorageKind::Sqlite => match sqlite::SqliteStore::open() {
+                Ok(store) => Box::new(store),
+                Err(err) => {
+                    let error = format!("cannot open state.db: {}", err);
+                    eprintln!("j: {}; history and aliases are unavailable", error);
+                    Box::new(Unavailable { error })
+                }
+            },
+            _ => Box::new(json::JsonStore),
+        })
+        .as_ref()
+}
+
+/// Load state, with the history of other machines read for ranking when a
+/// sync directory is configured
+pub fn load_state() -> AppState {
+    let mut state = store().load_state();
+    sync::read_hosts(&mut state, &load_config());
+    state
+}
+
+/// Save state, then publish this machine's history to the sync directory.
+/// The published copy is read back from storage so history merged in from
+/// other machines is never written out again under this host's name.
+pub fn save_state(state: &mut AppState) -> io::Result<()> {
+    store().save_state(state)?;
+    publish(&load_config())
+}
+
+/// Record a visit without loading the rest of the state, then publish
+pub fn record_visit(path: &str, config: &Config) -> io::Result<()> {
+    store().record_visit(path, config)?;
+    publish(config)
+}
+
+/// Remove a directory from history, then publish if it was there
+pub fn forget(path: &str) -> io::Result<bool> {
+    let removed = store().forget(path)?;
+    if removed {
+        publish(&load_config())?;
+    }
+    Ok(removed)
+}
+
+fn publish(config: &Config) -> io::Result<()> {
+    if sync::sync_dir(config).is_some() {
+        sync::publish(&mut store().load_state(), config)?;
+    }
+    Ok(())
+}
+
+pub fn load_aliases() -> Aliases {
+    store().load_aliases()
+}
+
+pub fn save_aliases(aliases: &Aliases) -> io::Result<()> {
+    store().save_aliases(aliases)
+}
+
+pub fn load_config() -> Config {
+    store().load_config()
+}
+
+pub fn save_config(config: &Config) -> io::Result<()> {
+    store().save_config(config)
+}
@@ -0,0 +1,542 @@
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, TransactionBehavior};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use super::json::JsonStore;
+use super::{added_visits, get_data_dir, index_by_path, removed_here, Store};
+use crate::aging::{self, Presence};
+use crate::rank;
+use crate::{Aliases, AppState, Config, EvictionPolicy, HistoryEntry};
+
+/// `MIGRATIONS[i]` upgrades the database from `PRAGMA user_version` i to i + 1
+const MIGRATIONS: &[&str] = &["
+    CREATE TABLE history (
+        path TEXT PRIMARY KEY COLLATE NOCASE,
+        last_visited TEXT NOT NULL,
+        visit_count INTEGER NOT NULL
+    );
+    CREATE INDEX history_last_visited ON history (last_visited);
+    CREATE TABLE stack (
+        kind TEXT NOT NULL,
+        position INTEGER NOT NULL,
+        path TEXT NOT NULL,
+        PRIMARY KEY (kind, position)
+    );
+    CREATE TABLE meta (key TEXT PRIMARY KEY, value TEXT);
+    CREATE TABLE aliases (name TEXT PRIMARY KEY, path TEXT NOT NULL);
+", "
+    ALTER TABLE history ADD COLUMN missing_since TEXT;
+", "
+    CREATE TABLE history_totals (entries INTEGER NOT NULL, visits INTEGER NOT NULL);
+    INSERT INTO history_totals SELECT COUNT(*), COALESCE(SUM(visit_count), 0) FROM history;
+    CREATE TRIGGER history_totals_insert AFTER INSERT ON history BEGIN
+        UPDATE history_totals SET entries = entries + 1, visits = visits + NEW.visit_count;
+    END;
+    CREATE TRIGGER history_totals_delete AFTER DELETE ON history BEGIN
+        UPDATE history_totals SET entries = entries - 1, visits = visits - OLD.visit_count;
+    END;
+    CREATE TRIGGER history_totals_update AFTER UPDATE OF visit_count ON history BEGIN
+        UPDATE history_totals SET visits = visits - OLD.visit_count + NEW.visit_count;
+    END;
+"];
+
+/// Schema version written by this binary
+const SCHEMA_VERSION: u32 = MIGRATIONS.len() as u32;
+
+/// How long to wait for another shell holding the database lock
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Rows read at a time by the existence checks of an aging pass
+const AGING_BATCH: i64 = 64;
+
+fn to_io(err: rusqlite::Error) -> io::Error {
+    io::Error::other(err)
+}
+
+/// History and aliases in a single SQLite database (`state.db`).
+/// Saving upserts only the history rows this process changed, and recording
+/// or forgetting one directory touches only its row through the primary key.
+/// Config stays in config.json.
+pub struct SqliteStore {
+    path: PathBuf,
+    conn: Mutex<Connection>,
+    /// Schema version found on open; writes are refused if it is newer than ours
+    version: u32,
+}
+
+impl SqliteStore {
+    pub fn open() -> io::Result<Self> {
+        let path = get_data_dir()?.join("state.db");
+        let mut conn = Connection::open(&path).map_err(to_io)?;
+        conn.busy_timeout(BUSY_TIMEOUT).map_err(to_io)?;
+
+        let version = Self::migrate(&path, &mut conn).map_err(to_io)?;
+        Ok(Self {
+            path,
+            conn: Mutex::new(conn),
+            version,
+        })
+    }
+
+    /// Bring the schema up to date, backing up an existing database first.
+    /// A new database is seeded from the JSON files in the same transaction,
+    /// so a second shell opening it at the same time waits and then finds
+    /// it already filled. Returns the schema version found before migrating.
+    fn migrate(path: &Path, conn: &mut Connection) -> rusqlite::Result<u32> {
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let version: u32 = tx.pragma_query_value(None, "user_version", |row| row.get(0))?;
+        if version >= SCHEMA_VERSION {
+            return Ok(version);
+        }
+        if version > 0 {
+            let backups = path.with_file_name("backups");
+            let name = format!("state.db.{}", Utc::now().format("%Y%m%d%H%M%S"));
+            if fs::create_dir_all(&backups).is_ok() {
+                fs::copy(path, backups.join(name)).ok();
+            }
+        }
+        for migration in &MIGRATIONS[version as usize..] {
+            tx.execute_batch(migration)?;
+        }
+        if version == 0 {
+            Self::import_json(&tx)?;
+        }
+        tx.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+        tx.commit()?;
+        Ok(version)
+    }
+
+    /// Seed a new database from the JSON files so switching backends keeps history
+    fn import_json(conn: &Connection) -> rusqlite::Result<()> {
+        let state = JsonStore.load_state();
+        {
+            let mut insert = conn.prepare(
+                "INSERT INTO history (path, last_visited, visit_count, missing_since)
+                 VALUES (?1, ?2, ?3, ?4) ON CONFLICT (path) DO NOTHING",
+            )?;
+            for entry in &state.history {
+                insert.execute(params![
+                    entry.path,
+                    entry.last_visited,
+                    entry.visit_count,
+                    entry.missing_since
+                ])?;
+            }
+        }
+        Self::write_stacks(conn, &state)?;
+        let mut insert =
+            conn.prepare("INSERT OR REPLACE INTO aliases (name, path) VALUES (?1, ?2)")?;
+        for (name, path) in &JsonStore.load_aliases().map {
+            insert.execute([name, path])?;
+        }
+        Ok(())
+    }
+
+    fn check_writable(&self) -> io::Result<()> {
+        if self.version > SCHEMA_VERSION {
+            return Err(io::Error::other(format!(
+                "{} uses schema version {}, newer than the supported {}; refusing to write to it",
+                self.path.display(),
+                self.version,
+                SCHEMA_VERSION
+            )));
+        }
+        Ok(())
+    }
+
+    /// Run SQLite's integrity check and describe the database
+    pub fn check(&self) -> Result<String, String> {
+        let conn = self.conn.lock().map_err(|err| err.to_string())?;
+        let result: String = conn
+            .query_row("PRAGMA integrity_check", [], |row| row.get(0))
+            .map_err(|err| err.to_string())?;
+        if result != "ok" {
+            return Err(format!("{}: {}", self.path.display(), result));
+        }
+        drop(conn);
+        self.check_writable().map_err(|err| err.to_string())?;
+        Ok(format!(
+            "{} (schema v{})",
+            self.path.display(),
+            self.version.max(SCHEMA_VERSION)
+        ))
+    }
+
+    fn read_state(conn: &Connection) -> rusqlite::Result<AppState> {
+        let mut state = AppState::default();
+
+        let mut stmt = conn.prepare(
+            "SELECT path, last_visited, visit_count, missing_since FROM history ORDER BY rowid",
+        )?;
+        state.history = stmt
+            .query_map([], |row| {
+                Ok(HistoryEntry {
+                    path: row.get(0)?,
+                    last_visited: row.get(1)?,
+                    visit_count: row.get(2)?,
+                    missing_since: row.get(3)?,
+                })
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+
+        let mut stmt = conn.prepare("SELECT path FROM stack WHERE kind = ?1 ORDER BY position")?;
+        state.undo_stack = stmt
+            .query_map(["undo"], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+        state.redo_stack = stmt
+            .query_map(["redo"], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+
+        state.current_dir = conn
+            .query_row(
+                "SELECT value FROM meta WHERE key = 'current_dir'",
+                [],
+                |row| row.get(0),
+            )
+            .ok();
+        state.aged_out = Self::read_aged_out(conn);
+        Ok(state)
+    }
+
+    /// Totals of the entries aging has dropped, kept in `meta` as JSON
+    fn read_aged_out(conn: &Connection) -> aging::Dropped {
+        conn.query_row("SELECT value FROM meta WHERE key = 'aged_out'", [], |row| {
+            row.get::<_, String>(0)
+        })
+        .ok()
+        .and_then(|value| serde_json::from_str(&value).ok())
+        .unwrap_or_default()
+    }
+
+    /// Aging pass over the database, touching only the rows it changes. The
+    /// visit total is kept by triggers, and existence checks resume at the
+    /// rowid where the previous pass stopped, so nothing scans the whole table
+    /// unless counts are actually scaled.
+    fn age(conn: &Connection, config: &Config) -> rusqlite::Result<()> {
+        let mut dropped = aging::Dropped::default();
+        let total: u64 =
+            conn.query_row("SELECT visits FROM history_totals", [], |row| row.get(0))?;
+        if let Some(factor) = aging::scale_factor(total, config) {
+            let rows: Vec<(String, u32)> = conn
+                .prepare("SELECT path, visit_count FROM history")?
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+                .collect::<rusqlite::Result<_>>()?;
+            for (path, count) in rows {
+                match aging::scale_count(count, factor, config) {
+                    Some(count) => conn.execute(
+                        "UPDATE history SET visit_count = ?2 WHERE path = ?1",
+                        params![path, count],
+                    )?,
+                    None => {
+                        dropped.rare += 1;
+                        conn.execute("DELETE FROM history WHERE path = ?1", [&path])?
+                    }
+                };
+            }
+        }
+
+        let now = Utc::now();
+        let budget = aging::Budget::new(config);
+        let start: i64 = conn
+            .query_row(
+                "SELECT value FROM meta WHERE key = 'aging_cursor'",
+                [],
+                |row| row.get::<_, String>(0),
+            )
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(0);
+        let mut select = conn.prepare(
+            "SELECT rowid, path, missing_since FROM history WHERE rowid > ?1 ORDER BY rowid LIMIT ?2",
+        )?;
+        let mut cursor = start;
+        let mut wrapped = false;
+        'scan: while !budget.exhausted() {
+            let rows: Vec<(i64, String, Option<DateTime<Utc>>)> = select
+                .query_map(params![cursor, AGING_BATCH], |row| {
+                    Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+                })?
+                .collect::<rusqlite::Result<_>>()?;
+            if rows.is_empty() {
+                // Past the last row: start over once, up to where this pass began
+                cursor = 0;
+                if wrapped || start == 0 {
+                    break;
+                }
+                wrapped = true;
+                continue;
+            }
+            for (rowid, path, missing_since) in rows {
+                if budget.exhausted() || (wrapped && rowid > start) {
+                    break 'scan;
+                }
+                cursor = rowid;
+                if Self::update_presence(conn, &path, missing_since, now, config)? {
+                    dropped.missing += 1;
+                }
+            }
+        }
+        conn.execute(
+            "INSERT OR REPLACE INTO meta (key, value) VALUES ('aging_cursor', ?1)",
+            [cursor.to_string()],
+        )?;
+
+        if !dropped.is_empty() {
+            dropped.last = Some(now);
+            let mut total = Self::read_aged_out(conn);
+            total.add(&dropped);
+            conn.execute(
+                "INSERT OR REPLACE INTO meta (key, value) VALUES ('aged_out', ?1)",
+                [serde_json::to_string(&total)
+                    .map_err(|err| rusqlite::Error::ToSqlConversionFailure(Box::new(err)))?],
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Record the outcome of one existence check. Returns whether the entry
+    /// was dropped.
+    fn update_presence(
+        conn: &Connection,
+        path: &str,
+        missing_since: Option<DateTime<Utc>>,
+        now: DateTime<Utc>,
+        config: &Config,
+    ) -> rusqlite::Result<bool> {
+        match aging::check_presence(path, missing_since, now, config) {
+            Presence::Present if missing_since.is_none() => {}
+            Presence::Present => {
+                conn.execute(
+                    "UPDATE history SET missing_since = NULL WHERE path = ?1",
+                    [path],
+                )?;
+            }
+            Presence::Missing(since) if missing_since == Some(since) => {}
+            Presence::Missing(since) => {
+                conn.execute(
+                    "UPDATE history SET missing_since = ?2 WHERE path = ?1",
+                    params![path, since],
+                )?;
+            }
+            Presence::Expired => {
+                conn.execute("DELETE FROM history WHERE path = ?1", [path])?;
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Drop entries beyond `max_history` by the eviction policy, as
+    /// `trim_history` does in memory. The row count is kept by triggers, so
+    /// this reads nothing while the history has room.
+    fn evict(conn: &Connection, config: &Config) -> rusqlite::Result<()> {
+        let entries: usize =
+            conn.query_row("SELECT entries FROM history_totals", [], |row| row.get(0))?;
+        if entries <= config.max_history {
+            return Ok(());
+        }
+        let excess = entries - config.max_history;
+        let victims: Vec<String> = match config.eviction {
+            EvictionPolicy::Lru => conn
+                .prepare("SELECT path FROM history ORDER BY last_visited LIMIT ?1")?
+                .query_map([excess], |row| row.get(0))?
+                .collect::<rusqlite::Result<_>>()?,
+            EvictionPolicy::Lfu => conn
+                .prepare("SELECT path FROM history ORDER BY visit_count, last_visited LIMIT ?1")?
+                .query_map([excess], |row| row.get(0))?
+                .collect::<rusqlite::Result<_>>()?,
+            EvictionPolicy::Frecency => {
+                let now = Utc::now();
+                let mut rows: Vec<(f64, String)> = conn
+                    .prepare("SELECT path, last_visited, visit_count FROM history")?
+                    .query_map([], |row| {
+                        let entry = HistoryEntry {
+                            path: row.get(0)?,
+                            last_visited: row.get(1)?,
+                            visit_count: row.get(2)?,
+                            missing_since: None,
+                        };
+                        let score =
+                            rank::frecency(&entry, now, config.decay_half_life_days);
+                        Ok((score, entry.path))
+                    })?
+                    .collect::<rusqlite::Result<_>>()?;
+                rows.sort_by(|a, b| a.0.total_cmp(&b.0));
+                rows.into_iter()
+                    .take(excess)
+                    .map(|(_, path)| path)
+                    .collect()
+            }
+        };
+        let mut delete = conn.prepare("DELETE FROM history WHERE path = ?1")?;
+        for path in victims {
+            delete.execute([path])?;
+        }
+        Ok(())
+    }
+
+    fn write_stacks(conn: &Connection, state: &AppState) -> rusqlite::Result<()> {
+        conn.execute("DELETE FROM stack", [])?;
+        {
+            let mut insert =
+                conn.prepare("INSERT INTO stack (kind, position, path) VALUES (?1, ?2, ?3)")?;
+            for (kind, stack) in [("undo", &state.undo_stack), ("redo", &state.redo_stack)] {
+                for (position, path) in stack.iter().enumerate() {
+                    insert.execute(params![kind, position, path])?;
+                }
+            }
+        }
+        conn.execute(
+            "INSERT OR REPLACE INTO meta (key, value) VALUES ('current_dir', ?1)",
+            [&state.current_dir],
+        )?;
+        Ok(())
+    }
+
+    fn write_state(
+        conn: &mut Connection,
+        state: &AppState,
+        config: &Config,
+    ) -> rusqlite::Result<()> {
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+
+        let base_index = index_by_path(&state.loaded_history);
+        let ours_index = index_by_path(&state.history);
+        for base in &state.loaded_history {
+            if removed_here(&base_index, &ours_index, &base.path) {
+                tx.execute("DELETE FROM history WHERE path = ?1", [&base.path])?;
+            }
+        }
+
+        {
+            let mut upsert = tx.prepare(
+                "INSERT INTO history (path, last_visited, visit_count) VALUES (?1, ?2, ?3)
+                 ON CONFLICT (path) DO UPDATE SET
+                     visit_count = visit_count + excluded.visit_count,
+                     last_visited = max(last_visited, excluded.last_visited),
+                     missing_since = NULL",
+            )?;
+            for entry in &state.history {
+                let added = added_visits(&base_index, entry);
+                if added > 0 {
+                    upsert.execute(params![entry.path, entry.last_visited, added])?;
+                }
+            }
+        }
+
+        Self::age(&tx, config)?;
+        Self::write_stacks(&tx, state)?;
+        tx.commit()
+    }
+}
+
+impl Store for SqliteStore {
+    fn load_state(&self) -> AppState {
+        let conn = match self.conn.lock() {
+            Ok(conn) => conn,
+            Err(_) => return AppState::default(),
+        };
+        let mut state = Self::read_state(&conn).unwrap_or_else(|err| {
+            eprintln!("j: cannot read {}: {}", self.path.display(), err);
+            AppState::default()
+        });
+        state.loaded_history = state.history.clone();
+        state
+    }
+
+    fn save_state(&self, state: &mut AppState) -> io::Result<()> {
+        self.check_writable()?;
+        let mut conn = self
+            .conn
+            .lock()
+            .map_err(|err| io::Error::other(err.to_string()))?;
+        Self::write_state(&mut conn, state, &self.load_config()).map_err(to_io)?;
+        state.loaded_history = state.history.clone();
+        Ok(())
+    }
+
+    /// One upsert through the primary key instead of loading the history
+    fn record_visit(&self, path: &str, config: &Config) -> io::Result<()> {
+        self.check_writable()?;
+        let mut conn = self
+            .conn
+            .lock()
+            .map_err(|err| io::Error::other(err.to_string()))?;
+        let tx = conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .map_err(to_io)?;
+        tx.execute(
+            "INSERT INTO history (path, last_visited, visit_count) VALUES (?1, ?2, 1)
+             ON CONFLICT (path) DO UPDATE SET
+                 visit_count = visit_count + 1,
+                 last_visited = excluded.last_visited,
+                 missing_since = NULL",
+            params![path, Utc::now()],
+        )
+        .map_err(to_io)?;
+        Self::evict(&tx, config).map_err(to_io)?;
+        Self::age(&tx, config).map_err(to_io)?;
+        tx.commit().map_err(to_io)
+    }
+
+    fn forget(&self, path: &str) -> io::Result<bool> {
+        self.check_writable()?;
+        let conn = self
+            .conn
+            .lock()
+            .map_err(|err| io::Error::other(err.to_string()))?;
+        conn.execute("DELETE FROM history WHERE path = ?1", [path])
+            .map(|removed| removed > 0)
+            .map_err(to_io)
+    }
+
+    fn load_aliases(&self) -> Aliases {
+        let conn = match self.conn.lock() {
+            Ok(conn) => conn,
+            Err(_) => return Aliases::default(),
+        };
+        let result = conn.prepare("SELECT name, path FROM aliases").and_then(|mut stmt| {
+            stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+                .collect::<rusqlite::Result<_>>()
+        });
+        match result {
+            Ok(map) => Aliases { map },
+            Err(err) => {
+                eprintln!("j: cannot read {}: {}", self.path.display(), err);
+                Aliases::default()
+            }
+        }
+    }
+
+    fn save_aliases(&self, aliases: &Aliases) -> io::Result<()> {
+        self.check_writable()?;
+        let mut conn = self
+            .conn
+            .lock()
+            .map_err(|err| io::Error::other(err.to_string()))?;
+        let tx = conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .map_err(to_io)?;
+        tx.execute("DELETE FROM aliases", []).map_err(to_io)?;
+        for (name, path) in &aliases.map {
+            tx.execute(
+                "INSERT INTO aliases (name, path) VALUES (?1, ?2)",
+                [name, path],
+            )
+            .map_err(to_io)?;
+        }
+        tx.commit().map_err(to_io)
+    }
+
+    fn load_config(&self) -> Config {
+        JsonStore.load_config()
+    }
+
+    fn save_config(&self, config: &Config) -> io::Result<()> {
+        JsonStore.save_config(config)
+    }
+}
@@ -0,0 +1,111 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use super::json::{try_read_json, write_atomic};
+use crate::rewrite;
+use crate::schema;
+use crate::{expand_home, AppState, Config};
+
+/// Per-host files in the sync directory are named `state.<hostname>.json`
+const HOST_FILE_PREFIX: &str = "state.";
+const HOST_FILE_SUFFIX: &str = ".json";
+
+/// Name of this machine as used in its sync file: `J_HOSTNAME` if set, then
+/// the system host name. Characters unsafe in a file name become `_`.
+pub fn hostname() -> String {
+    let name = env::var("J_HOSTNAME")
+        .ok()
+        .or_else(|| env::var("COMPUTERNAME").ok())
+        .or_else(|| fs::read_to_string("/etc/hostname").ok())
+        .or_else(|| {
+            let output = Command::new("hostname").output().ok()?;
+            String::from_utf8(output.stdout).ok()
+        })
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "localhost".to_string());
+    name.chars()
+        .map(|c| {
+            if c.is_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// The configured sync directory, with `~` expanded
+pub fn sync_dir(config: &Config) -> Option<PathBuf> {
+    let dir = config.sync_dir.as_deref().filter(|dir| !dir.is_empty())?;
+    Some(expand_home(dir).unwrap_or_else(|| PathBuf::from(dir)))
+}
+
+/// The file this machine publishes its history to
+pub fn host_file(dir: &Path) -> PathBuf {
+    dir.join(format!(
+        "{}{}{}",
+        HOST_FILE_PREFIX,
+        hostname(),
+        HOST_FILE_SUFFIX
+    ))
+}
+
+/// Every host's file in the sync directory, this machine's included, sorted
+pub fn host_files(dir: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            name.len() > HOST_FILE_PREFIX.len() + HOST_FILE_SUFFIX.len()
+                && name.starts_with(HOST_FILE_PREFIX)
+                && name.ends_with(HOST_FILE_SUFFIX)
+        })
+        .map(|entry| entry.path())
+        .collect();
+    files.sort();
+    files
+}
+
+/// Read the history other machines published into `state.synced`, with
+/// `~` expanded to this home directory and `path_rewrites` applied. It only
+/// takes part in ranking, so the paths are not checked on disk. Unreadable
+/// files are skipped; `j --doctor` reports them.
+pub fn read_hosts(state: &mut AppState, config: &Config) {
+    let Some(dir) = sync_dir(config) else {
+        return;
+    };
+    let own = host_file(&dir);
+    let rewriter = rewrite::Rewriter::new(config);
+    for file in host_files(&dir).into_iter().filter(|file| *file != own) {
+        if let Ok(Some(other)) = try_read_json::<AppState>(&file) {
+            state
+                .synced
+                .extend(other.history.into_iter().map(|mut entry| {
+                    entry.path = rewriter.apply(&entry.path);
+                    entry
+                }));
+        }
+    }
+}
+
+/// Write this machine's own history into the sync directory for the others.
+/// Paths under the home directory are written as `~/...` so machines whose
+/// home directories differ still share them.
+pub fn publish(local: &mut AppState, config: &Config) -> io::Result<()> {
+    let Some(dir) = sync_dir(config) else {
+        return Ok(());
+    };
+    fs::create_dir_all(&dir)?;
+    local.aged_out = Default::default();
+    for entry in &mut local.history {
+        entry.path = rewrite::portable(&entry.path);
+    }
+    let json = serde_json::to_string_pretty(&schema::to_versioned_value(local)?)?;
+    write_atomic(&host_file(&dir), json.as_bytes())
+}
@@ -0,0 +1,133 @@
+use chrono::Utc;
+use std::path::Path;
+
+use crate::normalize::{normalize, normalize_parts};
+use crate::query::{Query, Term};
+use crate::rank;
+use crate::{is_excluded, AppState, Config};
+
+/// Number of suggestions printed when no single directory stands out
+pub const MAX_SUGGESTIONS: usize = 5;
+
+/// A history entry within typo distance of the query
+#[derive(Debug, Clone)]
+pub struct Suggestion {
+    pub path: String,
+    /// Total edit distance over all tokens
+    pub distance: usize,
+    pub score: f64,
+}
+
+/// Edit distance counting insertions, deletions, substitutions and
+/// transpositions of adjacent characters (optimal string alignment)
+pub fn damerau_levenshtein(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in d[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            d[i][j] = (d[i - 1][j] + 1)
+                .min(d[i][j - 1] + 1)
+                .min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+/// Largest distance still treated as a typo of a token this long
+fn max_distance(token: &str) -> usize {
+    match token.chars().count() {
+        0..=3 => 0,
+        4..=6 => 1,
+        7..=10 => 2,
+        _ => 3,
+    }
+}
+
+/// Distance from a term to a path component, if close enough to be a typo.
+/// Only plain terms may be misspelled; the others must match literally.
+fn term_distance(term: &Term, part: &str) -> Option<usize> {
+    if term.matches(part) {
+        return Some(0);
+    }
+    if !term.is_plain() {
+        return None;
+    }
+    let distance = damerau_levenshtein(&term.text, &part.to_lowercase());
+    (distance <= max_distance(&term.text)).then_some(distance)
+}
+
+/// Distance of a path to the query, with the same shape as normal matching:
+/// the last term against the final directory name, the others in order
+/// among the parents
+fn path_distance(path: &str, query: &Query, config: &Config) -> Option<usize> {
+    let last = query.terms.last()?;
+    if !last.is_plain() {
+        return None;
+    }
+    if query.rejects(&normalize(path, config)) {
+        return None;
+    }
+    let parts = normalize_parts(path, config);
+    let parts: Vec<&str> = parts.iter().map(String::as_str).collect();
+    let (dir_name, parents) = parts.split_last()?;
+    if !query.final_terms().all(|term| term.matches(dir_name)) {
+        return None;
+    }
+
+    let distance = damerau_levenshtein(&last.text, &dir_name.to_lowercase());
+    if distance > max_distance(&last.text) {
+        return None;
+    }
+    let mut total = distance;
+    let mut rest = parents.iter();
+    for term in query.parent_terms() {
+        total += rest.find_map(|part| term_distance(term, part))?;
+    }
+    Some(total)
+}
+
+/// Existing, non-excluded history entries within typo distance of the query,
+/// closest first and then by frecency
+pub fn suggest(state: &AppState, query: &Query, config: &Config) -> Vec<Suggestion> {
+    let now = Utc::now();
+    let mut suggestions: Vec<Suggestion> = state
+        .ranked_history()
+        .iter()
+        .filter(|entry| !is_excluded(&entry.path, config))
+        .filter_map(|entry| {
+            let distance = path_distance(&entry.path, query, config)?;
+            Some(Suggestion {
+                path: entry.path.clone(),
+                distance,
+                score: rank::frecency(entry, now, config.decay_half_life_days),
+            })
+        })
+        .filter(|s| Path::new(&s.path).is_dir())
+        .collect();
+    suggestions.sort_by(|a, b| {
+        a.distance
+            .cmp(&b.distance)
+            .then_with(|| b.score.total_cmp(&a.score))
+    });
+    suggestions
+}
+
+/// The only suggestion at the smallest distance, if there is exactly one
+pub fn clear_winner(suggestions: &[Suggestion]) -> Option<&Suggestion> {
+    match suggestions {
+        [first] => Some(first),
+        [first, second, ..] if first.distance < second.distance => Some(first),
+        _ => None,
+    }
+}
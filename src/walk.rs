@@ -0,0 +1,201 @@
+use std::collections::VecDeque;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::fuzzy;
+use crate::normalize::{normalize, normalize_parts};
+use crate::query::{Query, Term};
+use crate::rank::{self, MatchTier};
+use crate::{is_excluded, tokens_match_in_order, Config};
+
+/// One pattern of a `.gitignore` file
+struct IgnorePattern {
+    glob: String,
+    /// Contains a `/`, so it matches the path relative to the file's directory
+    anchored: bool,
+}
+
+/// Directory patterns from the `.gitignore` of one directory.
+/// Negations (`!`) are not supported and are skipped.
+struct GitIgnore {
+    base: PathBuf,
+    patterns: Vec<IgnorePattern>,
+}
+
+impl GitIgnore {
+    fn load(dir: &Path) -> Option<Self> {
+        let contents = fs::read_to_string(dir.join(".gitignore")).ok()?;
+        let patterns: Vec<IgnorePattern> = contents
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#') && !line.starts_with('!'))
+            .map(|line| {
+                let line = line.trim_end_matches('/');
+                let line = line.strip_prefix("**/").unwrap_or(line);
+                IgnorePattern {
+                    anchored: line.contains('/'),
+                    glob: line.trim_start_matches('/').to_string(),
+                }
+            })
+            .filter(|pattern| !pattern.glob.is_empty())
+            .collect();
+        (!patterns.is_empty()).then(|| GitIgnore {
+            base: dir.to_path_buf(),
+            patterns,
+        })
+    }
+
+    fn ignores(&self, dir: &Path) -> bool {
+        let name = dir.file_name().map(|n| n.to_string_lossy());
+        let relative = dir
+            .strip_prefix(&self.base)
+            .map(|rel| rel.to_string_lossy().replace('\\', "/"));
+        self.patterns.iter().any(|pattern| {
+            if pattern.anchored {
+                relative
+                    .as_deref()
+                    .is_ok_and(|rel| glob_match(&pattern.glob, rel))
+            } else {
+                name.as_deref()
+                    .is_some_and(|name| glob_match(&pattern.glob, name))
+            }
+        })
+    }
+}
+
+/// Shell-style match supporting `*` and `?`, neither crossing `/`
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == text[t] || (c == '?' && text[t] != '/') => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((bp, bt)) if text[bt] != '/' => {
+                    p = bp + 1;
+                    t = bt + 1;
+                    backtrack = Some((bp, bt + 1));
+                }
+                _ => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Outcome of a walk below the current directory
+pub struct Walk {
+    /// Best-matching directory, if any
+    pub found: Option<PathBuf>,
+    /// Directory entries read
+    pub visited: usize,
+    /// The entry budget ran out before the walk finished
+    pub truncated: bool,
+}
+
+/// Score of a directory name against the last term: the exact, partial and
+/// fuzzy tier weights used for history
+fn name_score(term: &Term, name: &str) -> Option<f64> {
+    if term.equals(name) {
+        Some(MatchTier::Exact.weight())
+    } else if term.matches(name) {
+        Some(MatchTier::Partial.weight())
+    } else if term.is_plain() {
+        fuzzy::quality(&term.text, name).map(|q| q * MatchTier::Fuzzy.weight())
+    } else {
+        None
+    }
+}
+
+/// Score a directory by its path relative to the walk root
+fn score(relative: &str, query: &Query, config: &Config) -> Option<f64> {
+    let last = query.terms.last()?;
+    if query.rejects(&normalize(relative, config)) {
+        return None;
+    }
+    let parts = normalize_parts(relative, config);
+    let parts: Vec<&str> = parts.iter().map(String::as_str).collect();
+    let (name, parents) = parts.split_last()?;
+    if !query.final_terms().all(|term| term.matches(name)) {
+        return None;
+    }
+    if !tokens_match_in_order(parents, &query.parent_terms(), |term, part| {
+        rank::fuzzy_matches(term, part)
+    }) {
+        return None;
+    }
+    name_score(last, name)
+}
+
+/// Breadth-first search of the directories below `root` for the query,
+/// at most `walk_max_depth` levels deep and reading at most
+/// `walk_max_entries` entries. Hidden directories, excluded paths and
+/// directories ignored by a `.gitignore` met on the way are skipped, and
+/// symlinks are not followed. The best score wins; ties go to the shortest path.
+pub fn search(root: &Path, query: &Query, config: &Config) -> Walk {
+    let mut walk = Walk {
+        found: None,
+        visited: 0,
+        truncated: false,
+    };
+    if query.is_empty() || config.walk_max_entries == 0 || config.walk_max_depth == 0 {
+        return walk;
+    }
+
+    let mut best: Option<(f64, PathBuf)> = None;
+    // Each queued directory carries the .gitignore files of its ancestors
+    let mut queue: VecDeque<(PathBuf, usize, Vec<Rc<GitIgnore>>)> = VecDeque::new();
+    queue.push_back((root.to_path_buf(), 0, Vec::new()));
+    'walk: while let Some((dir, depth, mut ignores)) = queue.pop_front() {
+        ignores.extend(GitIgnore::load(&dir).map(Rc::new));
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for entry in entries.flatten() {
+            if walk.visited >= config.walk_max_entries {
+                walk.truncated = true;
+                break 'walk;
+            }
+            walk.visited += 1;
+            if !entry.file_type().is_ok_and(|t| t.is_dir()) {
+                continue;
+            }
+            let path = entry.path();
+            let name = entry.file_name();
+            if name.to_string_lossy().starts_with('.') {
+                continue;
+            }
+            let path_str = path.to_string_lossy();
+            if is_excluded(&path_str, config) || ignores.iter().any(|i| i.ignores(&path)) {
+                continue;
+            }
+            if let Ok(relative) = path.strip_prefix(root) {
+                if let Some(score) = score(&relative.to_string_lossy(), query, config) {
+                    let better = best.as_ref().is_none_or(|(b, bp)| {
+                        score > *b
+                            || (score == *b && path.as_os_str().len() < bp.as_os_str().len())
+                    });
+                    if better {
+                        best = Some((score, path.clone()));
+                    }
+                }
+            }
+            if depth + 1 < config.walk_max_depth {
+                queue.push_back((path, depth + 1, ignores.clone()));
+            }
+        }
+    }
+    walk.found = best.map(|(_, path)| path);
+    walk
+}